@@ -0,0 +1,72 @@
+// Cuts holes in an object with an opacity texture, for things like leaves, fences and
+// decals that would be expensive to model. Since the mask is itself something that can be
+// hit, it works wherever other objects do, including inside a HittableList.
+
+use crate::hit::{Hit, HitRecord};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::texture::Texture;
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::rc::Rc;
+
+// How the opacity (the average of the texture's channels) decides whether a hit counts.
+#[derive(Clone, Copy, Debug)]
+pub enum AlphaMode {
+    // Hits where the opacity is below the threshold are ignored. Gives hard edges.
+    Threshold(f64),
+    // Hits are kept with probability equal to the opacity, so partially opaque areas
+    // average out to the right amount of coverage over many samples. The choice is a hash of
+    // the ray and the hit rather than a fresh random number, so the same ray always sees the
+    // same coverage, and a sampler that replays a path (see `mlt`) gets the same path back.
+    Stochastic,
+}
+
+pub struct AlphaMask {
+    object: Rc<dyn Hit>,
+    opacity: Rc<dyn Texture>,
+    mode: AlphaMode,
+}
+
+impl AlphaMask {
+    pub fn new(object: Rc<dyn Hit>, opacity: Rc<dyn Texture>, mode: AlphaMode) -> Self {
+        Self {
+            object: object,
+            opacity: opacity,
+            mode: mode,
+        }
+    }
+
+    fn is_opaque(&self, ray: &Ray, hit_record: &HitRecord) -> bool {
+        let value = self.opacity.value(hit_record.u(), hit_record.v(), hit_record.point());
+        let alpha = (value.x() + value.y() + value.z()) / 3.0;
+        match self.mode {
+            AlphaMode::Threshold(threshold) => alpha >= threshold,
+            AlphaMode::Stochastic => alpha > hash_to_unit(ray, hit_record.t()),
+        }
+    }
+}
+
+// A number in [0, 1) that looks random but only depends on the ray and the distance `t`
+// along it.
+fn hash_to_unit(ray: &Ray, t: f64) -> f64 {
+    let mut hasher = DefaultHasher::new();
+    for value in [ray.orig().x(), ray.orig().y(), ray.orig().z(), ray.dir().x(), ray.dir().y(), ray.dir().z(), t] {
+        value.to_bits().hash(&mut hasher);
+    }
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl Hit for AlphaMask {
+    // Keeps looking further along the ray until it finds a hit that isn't masked out.
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        let mut min = ray_t.min();
+        loop {
+            let hit_record = self.object.hit(ray, &Interval::new(min, ray_t.max()))?;
+            if self.is_opaque(ray, &hit_record) {
+                return Some(hit_record);
+            }
+            min = hit_record.t();
+        }
+    }
+}
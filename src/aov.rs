@@ -0,0 +1,106 @@
+// Arbitrary output variables: extra images written next to the rendered one, for denoising
+// and compositing. Each is averaged over the same samples as the pixel it belongs to.
+//
+// Surface AOVs describe the first surface each camera ray hits. Light AOVs split the light
+// the path tracer finds into parts that add up to the rendered image: by whether it took
+// one bounce or more to reach the camera, and by which light it came from.
+
+use crate::camera::MIN_T_TO_PREVENT_SHADOW_ACNE;
+use crate::color::Color;
+use crate::debug;
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::scene::Scene;
+
+pub const SURFACE_AOVS: [&str; 4] = ["albedo", "normal", "depth", "object-id"];
+
+// Names of the light AOVs for a scene with `light_count` lights, in the order `LightSplit`
+// lists them.
+pub fn light_aovs(light_count: usize) -> Vec<String> {
+    let mut names = vec![String::from("direct"), String::from("indirect")];
+    names.extend((0..light_count).map(|index| format!("light-{}", index)));
+    names.push(String::from("background"));
+    names
+}
+
+// Whether the AOV `name` holds colors, like the image does, rather than data such as normals,
+// depths or object ids that only look like colors. Only colors change with the color space.
+pub fn is_color(name: &str) -> bool {
+    !matches!(name, "normal" | "depth" | "object-id")
+}
+
+// Values of the surface AOVs along `ray`, in the order of `SURFACE_AOVS`. The albedo is the
+// attenuation of one scattered ray, which averages out to the surface's albedo over many
+// samples. The normal is the shading normal, so it includes normal and bump maps. Normals
+// and depth are shown as in the debug views. Rays that miss get the
+// background as their albedo and black for the rest.
+pub fn surface(scene: &Scene, ray: &Ray, focus_distance: f64, rng: &mut dyn rand::RngCore) -> [Color; 4] {
+    let black = Color::new(0.0, 0.0, 0.0);
+    let Some((object, hit_record)) = scene.world().hit_object(ray, &Interval::new(MIN_T_TO_PREVENT_SHADOW_ACNE, f64::MAX)) else {
+        return [scene.background(ray.dir()), black, black, black];
+    };
+    let albedo = match hit_record.material().scatter(ray, &hit_record, rng) {
+        Some(result) => *result.attenuation(),
+        None => black,
+    };
+    let normal = hit_record.material().shading_normal(&hit_record);
+    let distance = hit_record.t() * ray.dir().len();
+    let shade = focus_distance / (focus_distance + distance);
+    [albedo, 0.5 * (normal + Color::new(1.0, 1.0, 1.0)), Color::new(shade, shade, shade), debug::id_color(object as u64)]
+}
+
+// Light reaching the camera along one path, split into the light AOVs.
+pub struct LightSplit {
+    direct: Color,  // Light emitted by what the camera sees, or reaching it in one bounce
+    indirect: Color,
+    lights: Vec<Color>,  // From each of the scene's lights
+    background: Color,  // From the sky and emitting surfaces that aren't lights
+}
+
+impl LightSplit {
+    pub fn new(light_count: usize) -> Self {
+        Self {
+            direct: Color::new(0.0, 0.0, 0.0),
+            indirect: Color::new(0.0, 0.0, 0.0),
+            lights: vec![Color::new(0.0, 0.0, 0.0); light_count],
+            background: Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Adds light that took `bounces` bounces to get here, from light number `light` or from
+    // the background if None.
+    pub fn add(&mut self, color: &Color, bounces: u32, light: Option<usize>) {
+        if bounces <= 1 {
+            self.direct += *color;
+        } else {
+            self.indirect += *color;
+        }
+        match light.and_then(|index| self.lights.get_mut(index)) {
+            Some(total) => *total += *color,
+            None => self.background += *color,
+        }
+    }
+
+    // Scales everything so far by `factor`, as the path tracer does with the light a scattered
+    // ray brings back.
+    pub fn scale(&mut self, factor: &Color) {
+        self.map(|color| *factor * *color);
+    }
+
+    pub fn map(&mut self, f: impl Fn(&Color) -> Color) {
+        self.direct = f(&self.direct);
+        self.indirect = f(&self.indirect);
+        for color in self.lights.iter_mut() {
+            *color = f(color);
+        }
+        self.background = f(&self.background);
+    }
+
+    // The parts in the order of `light_aovs`.
+    pub fn values(&self) -> Vec<Color> {
+        let mut values = vec![self.direct, self.indirect];
+        values.extend_from_slice(&self.lights);
+        values.push(self.background);
+        values
+    }
+}
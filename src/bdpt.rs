@@ -0,0 +1,360 @@
+// Bidirectional path tracing, after Veach's thesis (1997) and the structure of pbrt. Each
+// camera sample traces one path from the camera and one from a light, then joins every
+// prefix of one to every prefix of the other. Each joined path is weighted with the balance
+// heuristic over all the ways the same path could have been built, so caustics and light
+// squeezing through small gaps come out far less noisy than from the camera alone.
+//
+// Materials that can't be evaluated (see `Material::evaluate`) act like mirrors: paths go
+// through them but are never joined at them. The sky is only found by camera paths leaving
+// the scene, and directional lights only by joining camera paths to them. Participating
+// media are ignored, and rendering is in RGB.
+
+use crate::camera::{Camera, MIN_T_TO_PREVENT_SHADOW_ACNE};
+use crate::color::Color;
+use crate::film::Film;
+use crate::hit::{Hit, HitRecord};
+use crate::interval::Interval;
+use crate::light::Light;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+
+enum Kind<'a> {
+    Camera,
+    Light(&'a dyn Light),
+    Surface { hit_record: Box<HitRecord>, incoming: Ray },
+}
+
+struct Vertex<'a> {
+    kind: Kind<'a>,
+    point: Vec3,
+    normal: Option<Vec3>,  // Geometric normal, for vertices on a surface
+    beta: Color,  // Throughput of the subpath from its start up to this vertex
+    delta: bool,  // The path left this vertex through a part of the material that can't be evaluated
+    pdf_forward: f64,  // Density per unit area of reaching this vertex from the one before
+    pdf_reverse: f64,  // Density per unit area of reaching this vertex from the one after
+}
+
+impl<'a> Vertex<'a> {
+    fn new(kind: Kind<'a>, point: Vec3, normal: Option<Vec3>, beta: Color) -> Self {
+        Self {
+            kind: kind,
+            point: point,
+            normal: normal,
+            beta: beta,
+            delta: false,
+            pdf_forward: 0.0,
+            pdf_reverse: 0.0,
+        }
+    }
+
+    fn surface(hit_record: HitRecord, incoming: Ray, beta: Color) -> Self {
+        let point = *hit_record.point();
+        let normal = *hit_record.geometric_normal();
+        Vertex::new(Kind::Surface { hit_record: Box::new(hit_record), incoming: incoming }, point, Some(normal), beta)
+    }
+
+    // Whether paths can be joined at this vertex.
+    fn is_connectible(&self) -> bool {
+        match &self.kind {
+            Kind::Camera | Kind::Light(_) => true,
+            // Evaluating returns None for materials that can't be evaluated in any direction.
+            Kind::Surface { hit_record, incoming } => hit_record.material().evaluate(incoming, hit_record, hit_record.normal()).is_some(),
+        }
+    }
+
+    // Lights that sit at a single point can't be hit by paths from the camera.
+    fn is_delta_light(&self) -> bool {
+        matches!(self.kind, Kind::Light(_)) && self.normal.is_none()
+    }
+
+    // The material's BSDF times the cosine towards `point`, for surface vertices.
+    fn evaluate(&self, point: &Vec3) -> Option<Color> {
+        match &self.kind {
+            Kind::Surface { hit_record, incoming } => hit_record.material().evaluate(incoming, hit_record, &(*point - self.point)),
+            _ => None,
+        }
+    }
+
+    // Converts a density per unit solid angle at this vertex into a density per unit area
+    // at `next`.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let to_next = next.point - self.point;
+        let distance_sq = to_next.len_sq();
+        if distance_sq <= 0.0 {
+            return 0.0;
+        }
+        match next.normal {
+            Some(normal) => pdf * normal.dot(&to_next).abs() / (distance_sq * distance_sq.sqrt()),
+            None => pdf / distance_sq,
+        }
+    }
+
+    // Density per unit area of continuing from this vertex to `next`, having arrived from
+    // `previous`.
+    fn pdf(&self, camera: &Camera, previous: Option<&Vertex>, next: &Vertex) -> f64 {
+        let direction = next.point - self.point;
+        let pdf = match &self.kind {
+            Kind::Camera => camera.pdf_direction(&self.point, &direction),
+            Kind::Light(light) => return self.pdf_light(*light, next),
+            Kind::Surface { hit_record, incoming } => match previous {
+                Some(previous) => hit_record.material().pdf(&Ray::new(previous.point, self.point - previous.point), hit_record, &direction),
+                None => hit_record.material().pdf(incoming, hit_record, &direction),
+            },
+        };
+        self.convert_density(pdf, next)
+    }
+
+    // Density per unit area of `light`, sitting at this vertex, sending light to `next`.
+    fn pdf_light(&self, light: &dyn Light, next: &Vertex) -> f64 {
+        let (_, pdf_direction) = light.pdf_emission(&self.point, &(next.point - self.point));
+        self.convert_density(pdf_direction, next)
+    }
+}
+
+// Estimates the light arriving along the camera ray `ray`. Light that paths from the lights
+// carry straight to the camera lands elsewhere on the image and is splatted onto `film`.
+pub fn radiance(camera: &Camera, scene: &Scene, ray: &Ray, film: &mut Film, rng: &mut dyn rand::RngCore) -> Color {
+    let max_depth = camera.max_depth() as usize;
+
+    let mut camera_path = vec![Vertex::new(Kind::Camera, *ray.orig(), None, Color::new(1.0, 1.0, 1.0))];
+    let pdf_direction = camera.pdf_direction(ray.orig(), ray.dir());
+    let mut total = random_walk(scene, ray, Color::new(1.0, 1.0, 1.0), pdf_direction, max_depth + 2, &mut camera_path, rng);
+
+    let mut light_path = vec![];
+    if let Some((light, probability)) = scene.lights().choose(rng)
+        && let Some(emission) = light.sample_emission(rng)
+        && emission.pdf_position() > 0.0 && emission.pdf_direction() > 0.0 {
+        let origin = *emission.ray().orig();
+        let normal = light.normal(&origin);
+        let mut vertex = Vertex::new(Kind::Light(light), origin, normal, *emission.radiance());
+        vertex.pdf_forward = probability * emission.pdf_position();
+        light_path.push(vertex);
+
+        let cos = normal.map_or(1.0, |normal| normal.dot(emission.ray().dir()).abs());
+        let beta = *emission.radiance() * (cos / (probability * emission.pdf_position() * emission.pdf_direction()));
+        random_walk(scene, emission.ray(), beta, emission.pdf_direction(), max_depth + 1, &mut light_path, rng);
+    }
+
+    for t in 1..=camera_path.len() {
+        for s in 0..=light_path.len() {
+            if s + t < 2 || (s == 1 && t == 1) || s + t - 2 > max_depth {
+                continue;
+            }
+            total += connect(camera, scene, &camera_path, &light_path, s, t, film, rng);
+        }
+        if t >= 2 && t - 1 <= max_depth {
+            total += directional_lighting(scene, &camera_path[t - 1], rng);
+        }
+    }
+    total
+}
+
+// Extends `path` by following `ray` until it leaves the scene, is absorbed or the path has
+// `max_vertices` vertices. `pdf_direction` is the density of the ray's direction per unit
+// solid angle. Returns the light from the background if the ray leaves the scene.
+fn random_walk<'a>(scene: &'a Scene, ray: &Ray, beta: Color, pdf_direction: f64, max_vertices: usize, path: &mut Vec<Vertex<'a>>, rng: &mut dyn rand::RngCore) -> Color {
+    let mut ray = Ray::new(*ray.orig(), *ray.dir());
+    let mut beta = beta;
+    let mut pdf_forward = pdf_direction;
+    while path.len() < max_vertices {
+        let Some(hit_record) = scene.world().hit(&ray, &Interval::new(MIN_T_TO_PREVENT_SHADOW_ACNE, f64::MAX)) else {
+            return beta * scene.background(ray.dir());
+        };
+        let material = hit_record.material();
+        let mut vertex = Vertex::surface(hit_record.clone(), Ray::new(*ray.orig(), *ray.dir()), beta);
+        vertex.pdf_forward = path[path.len() - 1].convert_density(pdf_forward, &vertex);
+        path.push(vertex);
+        if path.len() == max_vertices {
+            break;
+        }
+
+        let Some(result) = material.scatter(&ray, &hit_record, rng) else { break };
+        let n = path.len();
+        if result.evaluated() {
+            let wi = result.scattered().dir().unit_vec();
+            let wo = -ray.dir().unit_vec();
+            pdf_forward = material.pdf(&ray, &hit_record, &wi);
+            let pdf_reverse = material.pdf(&Ray::new(*hit_record.point() + wi, -wi), &hit_record, &wo);
+            path[n - 2].pdf_reverse = path[n - 1].convert_density(pdf_reverse, &path[n - 2]);
+        } else {
+            path[n - 1].delta = true;
+            pdf_forward = 0.0;
+            path[n - 2].pdf_reverse = 0.0;
+        }
+        beta = beta * *result.attenuation();
+        ray = Ray::new(*result.scattered().orig(), *result.scattered().dir());
+    }
+    Color::new(0.0, 0.0, 0.0)
+}
+
+// Joins the first `s` vertices of the light path to the first `t` of the camera path.
+fn connect(camera: &Camera, scene: &Scene, camera_path: &[Vertex], light_path: &[Vertex], s: usize, t: usize, film: &mut Film, rng: &mut dyn rand::RngCore) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+    if s == 0 {
+        // The camera path found a light by itself.
+        let Kind::Surface { hit_record, incoming } = &camera_path[t - 1].kind else { return black };
+        let emitted = hit_record.material().emitted(incoming, hit_record);
+        if emitted.is_near_zero() {
+            return black;
+        }
+        return camera_path[t - 1].beta * emitted * mis_weight(camera, scene, camera_path, light_path, None, s, t);
+    }
+
+    if t == 1 {
+        // A light path vertex seen directly by the camera.
+        let qs = &light_path[s - 1];
+        if !qs.is_connectible() {
+            return black;
+        }
+        let Some(connection) = camera.connect(&qs.point, rng) else { return black };
+        let Some(bsdf) = qs.evaluate(connection.lens_point()) else { return black };
+        let contribution = qs.beta * bsdf * connection.importance();
+        if contribution.is_near_zero() || !is_visible(scene, &qs.point, connection.lens_point()) {
+            return black;
+        }
+        let sampled = Vertex::new(Kind::Camera, *connection.lens_point(), None, Color::new(1.0, 1.0, 1.0));
+        let weight = mis_weight(camera, scene, camera_path, light_path, Some(&sampled), s, t);
+        let (x, y) = connection.raster();
+        film.add_splat(x, y, &(contribution * weight));
+        return black;
+    }
+
+    let pt = &camera_path[t - 1];
+    if !pt.is_connectible() {
+        return black;
+    }
+    if s == 1 {
+        // A fresh point on a light, sampled from the camera path's last vertex.
+        let Some((light, probability)) = scene.lights().choose(rng) else { return black };
+        let Some(sample) = light.sample(&pt.point, rng) else { return black };
+        if sample.pdf() <= 0.0 {
+            return black;
+        }
+        let Some(bsdf) = pt.evaluate(&(pt.point + *sample.direction())) else { return black };
+        let contribution = pt.beta * bsdf * *sample.radiance() / (sample.pdf() * probability);
+        let light_point = pt.point + sample.distance() * *sample.direction();
+        if contribution.is_near_zero() || !is_visible(scene, &pt.point, &light_point) {
+            return black;
+        }
+        let mut sampled = Vertex::new(Kind::Light(light), light_point, light.normal(&light_point), *sample.radiance());
+        sampled.pdf_forward = probability * light.pdf_emission(&light_point, &(pt.point - light_point)).0;
+        return contribution * mis_weight(camera, scene, camera_path, light_path, Some(&sampled), s, t);
+    }
+
+    let qs = &light_path[s - 1];
+    if !qs.is_connectible() {
+        return black;
+    }
+    let (Some(bsdf_q), Some(bsdf_p)) = (qs.evaluate(&pt.point), pt.evaluate(&qs.point)) else { return black };
+    let contribution = qs.beta * bsdf_q * bsdf_p * pt.beta / (pt.point - qs.point).len_sq();
+    if contribution.is_near_zero() || !is_visible(scene, &qs.point, &pt.point) {
+        return black;
+    }
+    contribution * mis_weight(camera, scene, camera_path, light_path, None, s, t)
+}
+
+// Light from directional lights reaching the camera through `vertex`. No other strategy can
+// find these lights, so there's nothing to weigh this against.
+fn directional_lighting(scene: &Scene, vertex: &Vertex, rng: &mut dyn rand::RngCore) -> Color {
+    let mut total = Color::new(0.0, 0.0, 0.0);
+    if !vertex.is_connectible() {
+        return total;
+    }
+    for light in scene.lights().infinite_lights() {
+        let Some(sample) = light.sample(&vertex.point, rng) else { continue };
+        let Some(bsdf) = vertex.evaluate(&(vertex.point + *sample.direction())) else { continue };
+        let shadow_ray = Ray::new(vertex.point, *sample.direction());
+        if scene.world().hit(&shadow_ray, &Interval::new(MIN_T_TO_PREVENT_SHADOW_ACNE, f64::MAX)).is_some() {
+            continue;
+        }
+        total += vertex.beta * bsdf * *sample.radiance() / sample.pdf();
+    }
+    total
+}
+
+fn is_visible(scene: &Scene, from: &Vec3, to: &Vec3) -> bool {
+    let direction = *to - *from;
+    let distance = direction.len();
+    let shadow_ray = Ray::new(*from, direction / distance);
+    scene.world().hit(&shadow_ray, &Interval::new(MIN_T_TO_PREVENT_SHADOW_ACNE, distance - MIN_T_TO_PREVENT_SHADOW_ACNE)).is_none()
+}
+
+// Balance heuristic weight of the strategy joining `s` light vertices to `t` camera vertices,
+// where `sampled` replaces the last vertex on the side that has just one. The densities of
+// the vertices next to the join depend on the join, so they are recomputed here.
+fn mis_weight(camera: &Camera, scene: &Scene, camera_path: &[Vertex], light_path: &[Vertex], sampled: Option<&Vertex>, s: usize, t: usize) -> f64 {
+    if s + t == 2 {
+        return 1.0;
+    }
+
+    let pt = if t == 1 { sampled.unwrap_or(&camera_path[0]) } else { &camera_path[t - 1] };
+    let qs = match s {
+        0 => None,
+        1 => Some(sampled.unwrap_or(&light_path[0])),
+        _ => Some(&light_path[s - 1]),
+    };
+    let pt_minus = if t > 1 { Some(&camera_path[t - 2]) } else { None };
+    let qs_minus = if s > 1 { Some(&light_path[s - 2]) } else { None };
+
+    // (pdf_forward, pdf_reverse, delta) along each subpath.
+    let densities = |v: &Vertex| (v.pdf_forward, v.pdf_reverse, v.delta);
+    let mut camera_densities: Vec<(f64, f64, bool)> = camera_path[..t].iter().map(densities).collect();
+    let mut light_densities: Vec<(f64, f64, bool)> = light_path[..s].iter().map(densities).collect();
+    camera_densities[t - 1] = densities(pt);
+    camera_densities[t - 1].2 = false;
+    if let Some(qs) = qs {
+        light_densities[s - 1] = densities(qs);
+        light_densities[s - 1].2 = false;
+    }
+
+    match qs {
+        Some(qs) => {
+            camera_densities[t - 1].1 = qs.pdf(camera, qs_minus, pt);
+            if let Some(pt_minus) = pt_minus {
+                camera_densities[t - 2].1 = pt.pdf(camera, Some(qs), pt_minus);
+            }
+            light_densities[s - 1].1 = pt.pdf(camera, pt_minus, qs);
+            if let Some(qs_minus) = qs_minus {
+                light_densities[s - 2].1 = qs.pdf(camera, Some(pt), qs_minus);
+            }
+        },
+        None => {
+            // The camera path ends on a light, which a light path could have started from.
+            let Kind::Surface { hit_record, .. } = &pt.kind else { return 1.0 };
+            let Some((light, probability)) = hit_record.light().and_then(|index| scene.lights().find(index)) else { return 1.0 };
+            let Some(pt_minus) = pt_minus else { return 1.0 };
+            camera_densities[t - 1].1 = probability * light.pdf_emission(&pt.point, &(pt_minus.point - pt.point)).0;
+            camera_densities[t - 2].1 = pt.pdf_light(light, pt_minus);
+        },
+    }
+
+    let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+    let mut sum = 0.0;
+
+    // Strategies with fewer camera vertices.
+    let mut ratio = 1.0;
+    for i in (1..t).rev() {
+        ratio *= remap(camera_densities[i].1) / remap(camera_densities[i].0);
+        if !camera_densities[i].2 && !camera_densities[i - 1].2 {
+            sum += ratio;
+        }
+    }
+
+    // Strategies with fewer light vertices.
+    let light_is_delta = match s {
+        0 => false,
+        1 => qs.is_some_and(|qs| qs.is_delta_light()),
+        _ => light_path[0].is_delta_light(),
+    };
+    let mut ratio = 1.0;
+    for i in (0..s).rev() {
+        ratio *= remap(light_densities[i].1) / remap(light_densities[i].0);
+        let previous_is_delta = if i > 0 { light_densities[i - 1].2 } else { light_is_delta };
+        if !light_densities[i].2 && !previous_is_delta {
+            sum += ratio;
+        }
+    }
+
+    1.0 / (1.0 + sum)
+}
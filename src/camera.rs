@@ -1,15 +1,23 @@
-use crate::color::{Color, color_to_string};
-use crate::hit::Hit;
-use crate::hittable_list::HittableList;
+use crate::aov::{self, LightSplit};
+use crate::bdpt;
+use crate::color::{Color, xyz_to_linear_srgb};
+use crate::debug;
+use crate::film::Film;
+use crate::filter::{Filter, FilterKind};
+use crate::hit::{Hit, HitRecord};
 use crate::interval::Interval;
+use crate::medium::MediumSample;
+use crate::mlt;
 use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::spectrum::SampledWavelengths;
+use crate::sppm;
 use crate::util::{degrees_to_radians, random};
 use crate::vec3::Vec3;
 
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use clap::ValueEnum;
 
-const MIN_T_TO_PREVENT_SHADOW_ACNE: f64 = 1e-3;
+pub const MIN_T_TO_PREVENT_SHADOW_ACNE: f64 = 1e-3;
 
 pub struct Camera {
     image_width: u32,
@@ -17,13 +25,73 @@ pub struct Camera {
     center: Vec3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
-    pixel_upper_left_loc: Vec3,
     samples_per_pixel: u32,
     max_depth: u32,  // guards against stack overflow from reflected rays
 
     defocus_angle_degrees: f64,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+
+    // When set, each camera path samples wavelengths instead of tracing RGB.
+    spectral: bool,
+    integrator: Integrator,
+
+    // For the photon mapping integrator.
+    photons_per_iteration: u32,
+    photon_radius: f64,  // Initial search radius around each pixel's visible point
+
+    // For the Metropolis integrator.
+    mlt_bootstrap_samples: u32,
+    mlt_chains: u32,
+
+    ao_distance: f64,  // How far away objects still occlude, for the ambient occlusion view
+
+    // When set, the path integrator also renders the AOVs in aov.rs.
+    aovs: bool,
+
+    filter: Filter,  // Spreads each camera sample over the pixels around it
+
+    // For projecting points back onto the image, for light paths that reach the camera.
+    forward: Vec3,  // Unit vector along the view direction
+    focus_distance: f64,
+    viewport_upper_left: Vec3,
+    viewport_area: f64,  // Area of the viewport scaled to unit distance from the camera
+}
+
+// How the light reaching each pixel is estimated.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Integrator {
+    // Paths traced from the camera, with direct sampling of the lights.
+    Path,
+    // Paths traced from both the camera and the lights and joined in every possible way.
+    Bdpt,
+    // Photons shot from the lights and gathered around the points the camera sees.
+    Sppm,
+    // Camera paths explored by Markov chains that linger on the bright ones.
+    Mlt,
+    // Views of what the camera rays hit, for debugging scenes. See debug.rs.
+    Ao,
+    Normals,
+    Depth,
+    MaterialId,
+    Uv,
+    Bounces,
+}
+
+// A way for light at some point to reach the camera.
+pub struct CameraConnection {
+    lens_point: Vec3,
+    raster: (f64, f64),  // Where the light lands on the image, in pixels
+
+    // Factor converting light arriving at the lens from the point into its contribution
+    // to the image, divided by the probability of picking the lens point.
+    importance: f64,
+}
+
+impl CameraConnection {
+    pub fn lens_point(&self) -> &Vec3 { &self.lens_point }
+    pub fn raster(&self) -> (f64, f64) { self.raster }
+    pub fn importance(&self) -> f64 { self.importance }
 }
 
 // Computes the image height and ensures that it's at least 1.
@@ -42,10 +110,16 @@ fn actual_aspect_ratio(image_width: u32, image_height: u32) -> f64 {
 }
 
 // Returns the vector to a random point in the [-.5,-.5]-[+.5,+.5] unit square.
-fn sample_square(rng: &mut rand::rngs::ThreadRng) -> Vec3 {
+fn sample_square(rng: &mut dyn rand::RngCore) -> Vec3 {
     Vec3::new(random(-0.5, 0.5, rng), random(-0.5, 0.5, rng), 0.0)
 }
 
+// Returns a random raster position (x, y) within the pixel at (col, row).
+fn sample_pixel(row: u32, col: u32, rng: &mut dyn rand::RngCore) -> (f64, f64) {
+    let offset = sample_square(rng);
+    (col as f64 + 0.5 + offset.x(), row as f64 + 0.5 + offset.y())
+}
+
 impl Camera {
     // view_up = the "up" vector as seen from the world frame
     // defocus_angle_degrees = variation angle of rays through each pixel, in degrees
@@ -74,9 +148,8 @@ impl Camera {
         let pixel_delta_u = viewport_u / (image_width as f64);  // horizontal / column
         let pixel_delta_v = viewport_v / (image_height as f64);  // vertical / row
 
-        // Location of the upper left pixel
+        // Location of the upper left corner of the image
         let viewport_upper_left = camera_center - (focus_distance * w) - (viewport_u / 2.0) - (viewport_v / 2.0);
-        let pixel_upper_left_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
 
         // Camera defocus disk basis vectors
         let defocus_radius = focus_distance * (degrees_to_radians(defocus_angle_degrees / 2.0)).tan();
@@ -89,77 +162,343 @@ impl Camera {
             center: camera_center,
             pixel_delta_u: pixel_delta_u,
             pixel_delta_v: pixel_delta_v,
-            pixel_upper_left_loc: pixel_upper_left_loc,
             samples_per_pixel: samples_per_pixel,
             max_depth: max_depth,
 
             defocus_angle_degrees: defocus_angle_degrees,
             defocus_disk_u: defocus_disk_u,
             defocus_disk_v: defocus_disk_v,
+
+            spectral: false,
+            integrator: Integrator::Path,
+
+            photons_per_iteration: image_width * image_height,
+            photon_radius: 0.1,
+
+            mlt_bootstrap_samples: 100_000,
+            mlt_chains: 1000,
+
+            ao_distance: 1.0,
+
+            aovs: false,
+
+            filter: Filter::new(FilterKind::Box, None),
+
+            forward: -w,
+            focus_distance: focus_distance,
+            viewport_upper_left: viewport_upper_left,
+            viewport_area: viewport_width * viewport_height / (focus_distance * focus_distance),
+        }
+    }
+
+    pub fn with_spectral(mut self, spectral: bool) -> Self {
+        self.spectral = spectral;
+        self
+    }
+
+    pub fn with_integrator(mut self, integrator: Integrator) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    // Photon mapping shoots `photons_per_iteration` photons in each iteration, or one per
+    // pixel if None, and starts gathering them within `photon_radius` of each visible point.
+    pub fn with_photons(mut self, photons_per_iteration: Option<u32>, photon_radius: f64) -> Self {
+        self.photons_per_iteration = photons_per_iteration.unwrap_or(self.image_width * self.image_height);
+        self.photon_radius = photon_radius;
+        self
+    }
+
+    // The Metropolis integrator estimates the image's brightness from
+    // `bootstrap_samples` paths, then runs `chains` Markov chains.
+    pub fn with_mlt(mut self, bootstrap_samples: u32, chains: u32) -> Self {
+        self.mlt_bootstrap_samples = bootstrap_samples;
+        self.mlt_chains = chains;
+        self
+    }
+
+    pub fn with_ao_distance(mut self, distance: f64) -> Self {
+        self.ao_distance = distance;
+        self
+    }
+
+    pub fn with_aovs(mut self, aovs: bool) -> Self {
+        self.aovs = aovs;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn image_width(&self) -> u32 { self.image_width }
+    pub fn image_height(&self) -> u32 { self.image_height }
+    pub fn samples_per_pixel(&self) -> u32 { self.samples_per_pixel }
+    pub fn max_depth(&self) -> u32 { self.max_depth }
+    pub fn photons_per_iteration(&self) -> u32 { self.photons_per_iteration }
+    pub fn photon_radius(&self) -> f64 { self.photon_radius }
+    pub fn mlt_bootstrap_samples(&self) -> u32 { self.mlt_bootstrap_samples }
+    pub fn mlt_chains(&self) -> u32 { self.mlt_chains }
+
+    // Where the ray from `lens_point` through `point` lands on the image, in pixels from the
+    // top left corner, or None if it misses the image.
+    fn raster_position(&self, lens_point: &Vec3, point: &Vec3) -> Option<(f64, f64)> {
+        let direction = *point - *lens_point;
+        let cos = direction.dot(&self.forward);
+        if cos <= 0.0 {
+            return None;
+        }
+        // All rays through a pixel sample meet at the plane of focus.
+        let on_focus_plane = *lens_point + (self.focus_distance / cos) * direction - self.viewport_upper_left;
+        let x = on_focus_plane.dot(&self.pixel_delta_u) / self.pixel_delta_u.len_sq();
+        let y = on_focus_plane.dot(&self.pixel_delta_v) / self.pixel_delta_v.len_sq();
+        if x < 0.0 || y < 0.0 || x >= self.image_width as f64 || y >= self.image_height as f64 {
+            return None;
+        }
+        Some((x, y))
+    }
+
+    // Probability density per unit solid angle of a camera ray from `lens_point` going in
+    // `direction`.
+    pub fn pdf_direction(&self, lens_point: &Vec3, direction: &Vec3) -> f64 {
+        if self.raster_position(lens_point, &(*lens_point + *direction)).is_none() {
+            return 0.0;
         }
+        let cos = direction.unit_vec().dot(&self.forward);
+        1.0 / (self.viewport_area * cos * cos * cos)
+    }
+
+    // Picks a point on the lens to carry light from `point` to the image.
+    pub fn connect(&self, point: &Vec3, rng: &mut dyn rand::RngCore) -> Option<CameraConnection> {
+        let lens_point = if self.defocus_angle_degrees <= 0.0 { self.center } else { self.sample_from_defocus_disk(rng) };
+        let raster = self.raster_position(&lens_point, point)?;
+        let to_point = *point - lens_point;
+        let cos = to_point.unit_vec().dot(&self.forward);
+        Some(CameraConnection {
+            lens_point: lens_point,
+            raster: raster,
+            importance: 1.0 / (self.viewport_area * cos * cos * cos * to_point.len_sq()),
+        })
     }
 
-    fn sample_from_defocus_disk(&self, rng: &mut rand::rngs::ThreadRng) -> Vec3 {
+    fn sample_from_defocus_disk(&self, rng: &mut dyn rand::RngCore) -> Vec3 {
         let point = Vec3::uniform_random_in_unit_disk(rng);
         self.center + (point.x() * self.defocus_disk_u) + (point.y() * self.defocus_disk_v)
     }
 
     // Constructs a camera ray originating from a random point on the defocus disk and
     // directed at a randomly sampled point around the pixel location (col, row).
-    fn get_ray(&self, row: u32, col: u32, rng: &mut rand::rngs::ThreadRng) -> Ray {
-        let offset = sample_square(rng);
-        let row = row as f64;
-        let col = col as f64;
+    pub fn get_ray(&self, row: u32, col: u32, rng: &mut dyn rand::RngCore) -> Ray {
+        let (x, y) = sample_pixel(row, col, rng);
+        self.get_ray_at(x, y, rng)
+    }
+
+    // Constructs a camera ray from a random point on the defocus disk through the raster
+    // position (x, y), in pixels from the top left corner of the image.
+    pub fn get_ray_at(&self, x: f64, y: f64, rng: &mut dyn rand::RngCore) -> Ray {
         let ray_origin = if self.defocus_angle_degrees <= 0.0 { self.center } else { self.sample_from_defocus_disk(rng) };
-        let pixel_sample = self.pixel_upper_left_loc + ((col + offset.x()) * self.pixel_delta_u) + ((row + offset.y()) * (self.pixel_delta_v));
+        let pixel_sample = self.viewport_upper_left + (x * self.pixel_delta_u) + (y * self.pixel_delta_v);
         Ray::new(ray_origin, pixel_sample - ray_origin)
     }
 
     // Computes the color produced by a ray hitting the world. If it doesn't, just
-    // render the background.
-    fn compute_ray_color(&self, ray: &Ray, depth: u32, world: &HittableList, rng: &mut rand::rngs::ThreadRng) -> Color {
+    // render the background. For rays carrying wavelengths the result holds the radiance
+    // at each of them rather than RGB. Light emitted by the surface the ray hits is left out
+    // unless `include_emitted` is set, for rays whose lights were already sampled directly;
+    // emission from surfaces that aren't among the scene's lights is counted regardless.
+    // With `split`, the result is also broken down into the light AOVs, which must be empty
+    // on the way in.
+    pub fn compute_ray_color(&self, ray: &Ray, depth: u32, scene: &Scene, include_emitted: bool, mut split: Option<&mut LightSplit>, rng: &mut dyn rand::RngCore) -> Color {
         if depth == self.max_depth {
             return Color::new(0.0, 0.0, 0.0);
         }
-        match world.hit(&ray, &Interval::new(MIN_T_TO_PREVENT_SHADOW_ACNE, f64::MAX)) {
+        match scene.world().hit(ray, &Interval::new(MIN_T_TO_PREVENT_SHADOW_ACNE, f64::MAX)) {
             Some(hit_record) => {
-                let scatter_result = hit_record.material().scatter(&ray, &hit_record, rng);
-                match scatter_result {
-                    Some(scatter_result) => {
-                        let color_from_scattered_ray = self.compute_ray_color(scatter_result.scattered(), depth+1, world, rng);
-                        *scatter_result.attenuation() * color_from_scattered_ray
+                // A ray reaching a surface from the inside may first have scattered or been
+                // absorbed in the medium filling the object.
+                let material = hit_record.material();
+                let (weight, color) = match material.interior() {
+                    Some(medium) if !hit_record.front_face() => match medium.sample(ray, hit_record.t() * ray.dir().len(), rng) {
+                        MediumSample::Scatter { scattered, weight } => {
+                            let scattered = scattered.with_wavelengths(ray.wavelengths().copied());
+                            (weight, self.compute_ray_color(&scattered, depth+1, scene, true, split.as_deref_mut(), rng))
+                        },
+                        MediumSample::Pass { weight } => (weight, self.shade_surface(ray, &hit_record, depth, scene, include_emitted, split.as_deref_mut(), rng)),
                     },
-                    None => Color::new(0.0, 0.0, 0.0),
+                    _ => return self.shade_surface(ray, &hit_record, depth, scene, include_emitted, split, rng),
+                };
+                if let Some(split) = split {
+                    split.scale(&weight);
                 }
+                weight * color
             },
             None => {
-                let unit_direction = Vec3::unit_vec(ray.dir());
-                let a = 0.5 * (unit_direction.y() + 1.0);  // interpolation variable
-                (1.0-a)*Color::new(1.0, 1.0, 1.0) + a*Color::new(0.5, 0.7, 1.0)
+                let background = scene.background(ray.dir());
+                let background = match ray.wavelengths() {
+                    Some(wavelengths) => wavelengths.lift_rgb(&background),
+                    None => background,
+                };
+                if let Some(split) = split {
+                    split.add(&background, depth, None);
+                }
+                background
+            }
+        }
+    }
+
+    // Light reaching the camera from the scene's lights after one bounce off the surface,
+    // found with shadow rays. Zero for materials that can't be evaluated.
+    pub fn direct_lighting(&self, ray: &Ray, hit_record: &HitRecord, scene: &Scene, rng: &mut dyn rand::RngCore) -> Color {
+        self.direct_lighting_by_light(ray, hit_record, scene, rng).iter().fold(Color::new(0.0, 0.0, 0.0), |total, (_, color)| total + *color)
+    }
+
+    // Direct lighting from each light sampled, with the number of the light.
+    fn direct_lighting_by_light(&self, ray: &Ray, hit_record: &HitRecord, scene: &Scene, rng: &mut dyn rand::RngCore) -> Vec<(usize, Color)> {
+        let material = hit_record.material();
+        let mut contributions = vec![];
+        for (light, sample) in scene.lights().sample(hit_record.point(), rng) {
+            let Some(bsdf) = material.evaluate(ray, hit_record, sample.direction()) else { continue };
+            if bsdf.is_near_zero() || sample.pdf() <= 0.0 {
+                continue;
+            }
+            let shadow_ray = Ray::new(*hit_record.point(), *sample.direction());
+            let unoccluded = Interval::new(MIN_T_TO_PREVENT_SHADOW_ACNE, sample.distance() - MIN_T_TO_PREVENT_SHADOW_ACNE);
+            if scene.world().hit(&shadow_ray, &unoccluded).is_some() {
+                continue;
             }
+            let contribution = match ray.wavelengths() {
+                Some(wavelengths) => wavelengths.lift_rgb(&bsdf) * wavelengths.lift_rgb(sample.radiance()),
+                None => bsdf * *sample.radiance(),
+            };
+            contributions.push((light, contribution / sample.pdf()));
         }
+        contributions
     }
 
-    pub fn render(&self, world: &HittableList, file: &mut BufWriter<File>, rng: &mut rand::rngs::ThreadRng) -> std::io::Result<()> {
-        write!(file, "P3\n{} {}\n255\n", self.image_width, self.image_height)?;
+    // Light leaving the surface where `ray` hit it: emission, direct lighting and whatever
+    // the scattered ray brings back.
+    fn shade_surface(&self, ray: &Ray, hit_record: &HitRecord, depth: u32, scene: &Scene, include_emitted: bool, mut split: Option<&mut LightSplit>, rng: &mut dyn rand::RngCore) -> Color {
+        let material = hit_record.material();
+        let direct = self.direct_lighting_by_light(ray, hit_record, scene, rng);
+        // Direct lighting only samples the scene's lights, so emission from surfaces that
+        // aren't one of them is always counted.
+        let emitted = material.emitted(ray, hit_record);
+        let emitted = if include_emitted || (!emitted.is_near_zero() && hit_record.light().is_none()) {
+            match ray.wavelengths() {
+                Some(wavelengths) => wavelengths.lift_rgb(&emitted),
+                None => emitted,
+            }
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        };
+        let mut color = self.scatter_at_surface(ray, hit_record, depth, scene, split.as_deref_mut(), rng) + emitted;
+        for (_, contribution) in direct.iter() {
+            color += *contribution;
+        }
+
+        // The scattered ray's light is in the split by now, so this surface's own goes last.
+        if let Some(split) = split {
+            if !emitted.is_near_zero() {
+                split.add(&emitted, depth, hit_record.light());
+            }
+            for (light, contribution) in direct.iter() {
+                split.add(contribution, depth + 1, Some(*light));
+            }
+        }
+        color
+    }
+
+    // Continues the path from where `ray` hit a surface.
+    fn scatter_at_surface(&self, ray: &Ray, hit_record: &HitRecord, depth: u32, scene: &Scene, mut split: Option<&mut LightSplit>, rng: &mut dyn rand::RngCore) -> Color {
+        let scatter_result = hit_record.material().scatter(ray, hit_record, rng);
+        let Some(scatter_result) = scatter_result else { return Color::new(0.0, 0.0, 0.0) };
+        let (attenuation, scattered) = match ray.wavelengths() {
+            None => (*scatter_result.attenuation(), Ray::new(*scatter_result.scattered().orig(), *scatter_result.scattered().dir())),
+            Some(wavelengths) => {
+                let mut attenuation = if scatter_result.spectral() {
+                    *scatter_result.attenuation()
+                } else {
+                    wavelengths.lift_rgb(scatter_result.attenuation())
+                };
+                let mut wavelengths = *wavelengths;
+                if scatter_result.dispersive() && !wavelengths.secondary_terminated() {
+                    attenuation = attenuation * SampledWavelengths::termination_weights();
+                    wavelengths = wavelengths.terminate_secondary();
+                }
+                (attenuation, Ray::new(*scatter_result.scattered().orig(), *scatter_result.scattered().dir()).with_wavelengths(Some(wavelengths)))
+            },
+        };
+        let color = self.compute_ray_color(&scattered, depth+1, scene, !scatter_result.evaluated(), split.as_deref_mut(), rng);
+        if let Some(split) = split {
+            split.scale(&attenuation);
+        }
+        attenuation * color
+    }
+
+    // Light arriving along a camera ray, found by the path integrator. See
+    // `compute_ray_color` for `split`.
+    fn path_color(&self, ray: &Ray, scene: &Scene, mut split: Option<&mut LightSplit>, rng: &mut dyn rand::RngCore) -> Color {
+        if !self.spectral {
+            return self.compute_ray_color(ray, 0, scene, true, split, rng);
+        }
+        let wavelengths = SampledWavelengths::sample(rng);
+        let radiance = self.compute_ray_color(&Ray::new(*ray.orig(), *ray.dir()).with_wavelengths(Some(wavelengths)), 0, scene, true, split.as_deref_mut(), rng);
+        if let Some(split) = split {
+            split.map(|radiance| xyz_to_linear_srgb(&wavelengths.estimate_xyz(radiance)));
+        }
+        xyz_to_linear_srgb(&wavelengths.estimate_xyz(&radiance))
+    }
+
+    pub fn render(&self, scene: &Scene, rng: &mut dyn rand::RngCore) -> Film {
+        match self.integrator {
+            Integrator::Sppm => return sppm::render(self, scene, rng),
+            Integrator::Mlt => return mlt::render(self, scene, rng),
+            _ => {},
+        }
+        let mut film = Film::new(self.image_width, self.image_height, self.samples_per_pixel).with_filter(self.filter);
+        if self.aovs {
+            for name in aov::SURFACE_AOVS {
+                film.add_aov(name);
+            }
+            for name in aov::light_aovs(scene.lights().len()) {
+                film.add_aov(&name);
+            }
+        }
 
         for row in 0..self.image_height {
             let scanlines_remaining = self.image_height - row;
-            if scanlines_remaining % 10 == 0 {
+            if scanlines_remaining.is_multiple_of(10) {
                 eprintln!("Scanlines remaining: {}", self.image_height - row);
             }
             for col in 0..self.image_width {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
                 for _ in 0..self.samples_per_pixel {
-                    let ray = self.get_ray(row, col, rng);
-                    pixel_color += self.compute_ray_color(&ray, 0, world, rng);
+                    let (x, y) = sample_pixel(row, col, rng);
+                    let ray = self.get_ray_at(x, y, rng);
+                    let mut values = vec![];
+                    let color = match self.integrator {
+                        Integrator::Path if self.aovs => {
+                            values = aov::surface(scene, &ray, self.focus_distance, rng).to_vec();
+                            let mut split = LightSplit::new(scene.lights().len());
+                            let color = self.path_color(&ray, scene, Some(&mut split), rng);
+                            values.extend(split.values());
+                            color
+                        },
+                        Integrator::Path => self.path_color(&ray, scene, None, rng),
+                        Integrator::Bdpt => bdpt::radiance(self, scene, &ray, &mut film, rng),
+                        Integrator::Sppm | Integrator::Mlt => unreachable!("rendered as a whole above"),
+                        Integrator::Ao => debug::ambient_occlusion(scene, &ray, self.ao_distance, rng),
+                        Integrator::Normals => debug::normals(scene, &ray),
+                        Integrator::Depth => debug::depth(scene, &ray, self.focus_distance),
+                        Integrator::MaterialId => debug::material_id(scene, &ray),
+                        Integrator::Uv => debug::uv(scene, &ray),
+                        Integrator::Bounces => debug::bounces(scene, &ray, self.max_depth, rng),
+                    };
+                    film.add_sample(x, y, &color, &values);
                 }
-                pixel_color /= self.samples_per_pixel as f64;
-                let color_bytes = color_to_string(&pixel_color);
-                write!(file, "{} {} {}\n", color_bytes.r(), color_bytes.g(), color_bytes.b())?;
             }
         }
 
-        Ok(())
+        film
     }
 }
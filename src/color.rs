@@ -1,37 +1,22 @@
+use crate::color_space::ColorSpace;
 use crate::interval::Interval;
 use crate::vec3;
 
 pub type Color = vec3::Vec3;
 
-pub struct ColorBytes {
-    r: u8,
-    g: u8,
-    b: u8,
+// Encodes the linear `color` with `space`'s transfer function and rounds each channel to an
+// integer from 0 to `max`, after adding `noise`, measured in steps of 1, for dithering.
+pub fn quantize(color: &Color, space: ColorSpace, max: u16, noise: [f64; 3]) -> [u16; 3] {
+    let range = Interval::new(0.0, max as f64);
+    let channel = |linear: f64, noise: f64| range.clamp((space.encode(linear) * max as f64 + noise).round()) as u16;
+    [channel(color.x(), noise[0]), channel(color.y(), noise[1]), channel(color.z(), noise[2])]
 }
 
-impl ColorBytes {
-    pub fn r(&self) -> u8 { self.r }
-    pub fn g(&self) -> u8 { self.g }
-    pub fn b(&self) -> u8 { self.b }
+// Converts CIE XYZ (relative to a D65 white) to linear sRGB.
+pub fn xyz_to_linear_srgb(xyz: &Color) -> Color {
+    Color::new(
+        3.2404542 * xyz.x() - 1.5371385 * xyz.y() - 0.4985314 * xyz.z(),
+        -0.9692660 * xyz.x() + 1.8760108 * xyz.y() + 0.0415560 * xyz.z(),
+        0.0556434 * xyz.x() - 0.2040259 * xyz.y() + 1.0572252 * xyz.z(),
+    )
 }
-
-// We don't implement fmt::Display here because that would conflict with the impl for Vec3.
-pub fn color_to_string(color: &Color) -> ColorBytes {
-    let k_intensity = Interval::new(0.0, 0.999);
-
-    let r = linear_to_gamma(color.x());
-    let g = linear_to_gamma(color.y());
-    let b = linear_to_gamma(color.z());
-    
-    ColorBytes {
-        r: (256.0 * k_intensity.clamp(r)) as u8,
-        g: (256.0 * k_intensity.clamp(g)) as u8,
-        b: (256.0 * k_intensity.clamp(b)) as u8,
-    }
-}
-
-// Transforms linear color to gamma space so that the human eye can more
-// better see darker tones.
-pub fn linear_to_gamma(linear_component: f64) -> f64 {
-    if linear_component > 0.0 { linear_component.sqrt() } else { 0.0 }
-}
\ No newline at end of file
@@ -0,0 +1,160 @@
+// RGB color spaces. Each is defined by the chromaticities of its red, green and blue
+// primaries and of its white, from which the matrices converting to and from CIE XYZ are
+// derived, and by the transfer function that encodes linear values for 8-bit images.
+//
+// The renderer works in linear RGB with the primaries of the working space: scene colors are
+// taken to be in it. Output converts to the output space.
+
+use crate::color::Color;
+
+use clap::ValueEnum;
+
+pub type Matrix = [[f64; 3]; 3];
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ColorSpace {
+    // The web and most monitors.
+    Srgb,
+    // HD video. Same primaries as sRGB, with the BT.709 transfer function.
+    Rec709,
+    // UHD video, with a much wider gamut.
+    Rec2020,
+    // The ACES working space for rendering and compositing. Its transfer function is linear,
+    // so it suits float outputs best.
+    Acescg,
+    // Wide gamut Apple displays, with the sRGB transfer function.
+    DisplayP3,
+}
+
+const D65: (f64, f64) = (0.3127, 0.3290);
+const D50: (f64, f64) = (0.3457, 0.3585);
+
+impl ColorSpace {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "sRGB",
+            ColorSpace::Rec709 => "Rec. 709",
+            ColorSpace::Rec2020 => "Rec. 2020",
+            ColorSpace::Acescg => "ACEScg",
+            ColorSpace::DisplayP3 => "Display P3",
+        }
+    }
+
+    // Chromaticities (x, y) of the red, green and blue primaries.
+    pub fn primaries(&self) -> [(f64, f64); 3] {
+        match self {
+            ColorSpace::Srgb | ColorSpace::Rec709 => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06)],
+            ColorSpace::Rec2020 => [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046)],
+            ColorSpace::Acescg => [(0.713, 0.293), (0.165, 0.830), (0.128, 0.044)],
+            ColorSpace::DisplayP3 => [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060)],
+        }
+    }
+
+    // Chromaticity (x, y) of the white point.
+    pub fn white(&self) -> (f64, f64) {
+        match self {
+            ColorSpace::Acescg => (0.32168, 0.33767),
+            _ => D65,
+        }
+    }
+
+    // Linear RGB in this space to XYZ.
+    pub fn rgb_to_xyz(&self) -> Matrix {
+        let primaries = self.primaries().map(chromaticity_to_xyz);
+        let columns = [[primaries[0].x(), primaries[1].x(), primaries[2].x()], [primaries[0].y(), primaries[1].y(), primaries[2].y()], [primaries[0].z(), primaries[1].z(), primaries[2].z()]];
+        // Scale each primary so that they add up to the white.
+        let scale = transform(&inverse(&columns), &chromaticity_to_xyz(self.white()));
+        let scale = [scale.x(), scale.y(), scale.z()];
+        columns.map(|row| [row[0] * scale[0], row[1] * scale[1], row[2] * scale[2]])
+    }
+
+    // Linear RGB in this space to linear RGB in `other`, adapting to its white.
+    pub fn conversion_to(&self, other: ColorSpace) -> Matrix {
+        let adaptation = bradford(self.white(), other.white());
+        multiply(&inverse(&other.rgb_to_xyz()), &multiply(&adaptation, &self.rgb_to_xyz()))
+    }
+
+    // Linear RGB in this space to XYZ relative to a D50 white, as ICC profiles expect.
+    pub fn rgb_to_d50_xyz(&self) -> Matrix {
+        multiply(&bradford(self.white(), D50), &self.rgb_to_xyz())
+    }
+
+    // The transfer function, from a linear value to the encoded one.
+    pub fn encode(&self, linear: f64) -> f64 {
+        let linear = linear.max(0.0);
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => {
+                if linear <= 0.0031308 { 12.92 * linear } else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 }
+            },
+            ColorSpace::Rec709 | ColorSpace::Rec2020 => {
+                if linear < 0.018 { 4.5 * linear } else { 1.099 * linear.powf(0.45) - 0.099 }
+            },
+            ColorSpace::Acescg => linear,
+        }
+    }
+
+    // The inverse of `encode`.
+    pub fn decode(&self, encoded: f64) -> f64 {
+        let encoded = encoded.max(0.0);
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => {
+                if encoded <= 0.04045 { encoded / 12.92 } else { ((encoded + 0.055) / 1.055).powf(2.4) }
+            },
+            ColorSpace::Rec709 | ColorSpace::Rec2020 => {
+                if encoded < 0.081 { encoded / 4.5 } else { ((encoded + 0.099) / 1.099).powf(1.0 / 0.45) }
+            },
+            ColorSpace::Acescg => encoded,
+        }
+    }
+}
+
+// XYZ of the color with chromaticity (x, y) and luminance 1.
+fn chromaticity_to_xyz((x, y): (f64, f64)) -> Color {
+    Color::new(x / y, 1.0, (1.0 - x - y) / y)
+}
+
+// Chromatic adaptation of XYZ from one white to another by the Bradford transform, which
+// scales the responses of the eye's cones.
+fn bradford(from: (f64, f64), to: (f64, f64)) -> Matrix {
+    const CONE: Matrix = [
+        [0.8951, 0.2664, -0.1614],
+        [-0.7502, 1.7135, 0.0367],
+        [0.0389, -0.0685, 1.0296],
+    ];
+    let from = transform(&CONE, &chromaticity_to_xyz(from));
+    let to = transform(&CONE, &chromaticity_to_xyz(to));
+    let scale = [[to.x() / from.x(), 0.0, 0.0], [0.0, to.y() / from.y(), 0.0], [0.0, 0.0, to.z() / from.z()]];
+    multiply(&inverse(&CONE), &multiply(&scale, &CONE))
+}
+
+pub fn transform(matrix: &Matrix, color: &Color) -> Color {
+    let row = |r: &[f64; 3]| r[0] * color.x() + r[1] * color.y() + r[2] * color.z();
+    Color::new(row(&matrix[0]), row(&matrix[1]), row(&matrix[2]))
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [[0.0; 3]; 3];
+    for (i, row) in product.iter_mut().enumerate() {
+        for (j, entry) in row.iter_mut().enumerate() {
+            *entry = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    product
+}
+
+// Assumes `m` is invertible, which the matrices of color spaces are.
+fn inverse(m: &Matrix) -> Matrix {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let determinant = m[0][0] * cofactor(0, 0) + m[0][1] * cofactor(0, 1) + m[0][2] * cofactor(0, 2);
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, entry) in row.iter_mut().enumerate() {
+            *entry = cofactor(j, i) / determinant;
+        }
+    }
+    inverse
+}
@@ -0,0 +1,86 @@
+// Integrators that show one property of what each camera ray hits instead of the light
+// arriving along it, for finding out why a scene looks wrong.
+
+use crate::camera::MIN_T_TO_PREVENT_SHADOW_ACNE;
+use crate::color::Color;
+use crate::hit::{Hit, HitRecord};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+
+fn first_hit(scene: &Scene, ray: &Ray) -> Option<HitRecord> {
+    scene.world().hit(ray, &Interval::new(MIN_T_TO_PREVENT_SHADOW_ACNE, f64::MAX))
+}
+
+// White where a cosine-weighted ray from the hit point escapes without meeting anything
+// within `distance`, black where it doesn't. Rays that hit nothing are white.
+pub fn ambient_occlusion(scene: &Scene, ray: &Ray, distance: f64, rng: &mut dyn rand::RngCore) -> Color {
+    let Some(hit_record) = first_hit(scene, ray) else { return Color::new(1.0, 1.0, 1.0) };
+    let mut direction = *hit_record.normal() + Vec3::uniform_random_unit_vec(rng);
+    if direction.is_near_zero() {
+        direction = *hit_record.normal();
+    }
+    let occlusion_ray = Ray::new(*hit_record.point(), direction.unit_vec());
+    match scene.world().hit(&occlusion_ray, &Interval::new(MIN_T_TO_PREVENT_SHADOW_ACNE, distance)) {
+        Some(_) => Color::new(0.0, 0.0, 0.0),
+        None => Color::new(1.0, 1.0, 1.0),
+    }
+}
+
+// The shading normal pointing out of the object, including normal and bump maps, with each
+// component mapped from [-1, 1] to [0, 1].
+pub fn normals(scene: &Scene, ray: &Ray) -> Color {
+    let Some(hit_record) = first_hit(scene, ray) else { return Color::new(0.0, 0.0, 0.0) };
+    let normal = hit_record.material().shading_normal(&hit_record);
+    0.5 * (normal + Color::new(1.0, 1.0, 1.0))
+}
+
+// Distance to the hit point, from white up close to black far away. Points at the camera's
+// focus distance are mid gray.
+pub fn depth(scene: &Scene, ray: &Ray, focus_distance: f64) -> Color {
+    let Some(hit_record) = first_hit(scene, ray) else { return Color::new(0.0, 0.0, 0.0) };
+    let distance = hit_record.t() * ray.dir().len();
+    let shade = focus_distance / (focus_distance + distance);
+    Color::new(shade, shade, shade)
+}
+
+// A color per material, so objects sharing one show the same color. The colors are picked
+// from the order the scene makes its materials in.
+pub fn material_id(scene: &Scene, ray: &Ray) -> Color {
+    let Some(hit_record) = first_hit(scene, ray) else { return Color::new(0.0, 0.0, 0.0) };
+    id_color(hit_record.material().id())
+}
+
+// A bright color that tells `id` apart from nearby values.
+pub fn id_color(id: u64) -> Color {
+    // SplitMix64's finalizer, which spreads nearby values over all the bits.
+    let mut hash = id.wrapping_add(0x9e3779b97f4a7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^= hash >> 31;
+    let channel = |shift: u32| 0.2 + 0.8 * ((hash >> shift) & 0xff) as f64 / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+// Surface coordinates of the hit point, u in red and v in green.
+pub fn uv(scene: &Scene, ray: &Ray) -> Color {
+    let Some(hit_record) = first_hit(scene, ray) else { return Color::new(0.0, 0.0, 0.0) };
+    Color::new(hit_record.u(), hit_record.v(), 0.0)
+}
+
+// How many times the path scatters before it leaves the scene, is absorbed or reaches
+// `max_depth`, as a heat map running from black through red and yellow to white at
+// `max_depth`.
+pub fn bounces(scene: &Scene, ray: &Ray, max_depth: u32, rng: &mut dyn rand::RngCore) -> Color {
+    let mut ray = Ray::new(*ray.orig(), *ray.dir());
+    let mut count = 0;
+    while count < max_depth {
+        let Some(hit_record) = first_hit(scene, &ray) else { break };
+        let Some(result) = hit_record.material().scatter(&ray, &hit_record, rng) else { break };
+        ray = Ray::new(*result.scattered().orig(), *result.scattered().dir());
+        count += 1;
+    }
+    let heat = 3.0 * count as f64 / max_depth.max(1) as f64;
+    Color::new(heat.clamp(0.0, 1.0), (heat - 1.0).clamp(0.0, 1.0), (heat - 2.0).clamp(0.0, 1.0))
+}
@@ -0,0 +1,82 @@
+// A joint bilateral filter that smooths the noise out of a rendered image. Each pixel becomes
+// a weighted average of its neighbors, and the weights fall off not only with distance but
+// with how much the neighbor's albedo, normal and depth AOVs differ from the pixel's. Those
+// are nearly noise free, so the filter blurs across a surface but stops at the edges of
+// objects and of textures.
+//
+// The filter works on the illumination, the color divided by the albedo, and multiplies the
+// albedo back in afterwards, so textures stay sharp even where the lighting gets blurred.
+
+use crate::color::Color;
+use crate::film::Film;
+
+// How far apart the guides of two pixels may be before they stop sharing light, at a
+// strength of 1. The normal and depth AOVs are mapped to [0, 1] like the albedo.
+const ALBEDO_SIGMA: f64 = 0.1;
+const NORMAL_SIGMA: f64 = 0.1;
+const DEPTH_SIGMA: f64 = 0.02;
+// The same for the illumination, on a logarithmic scale, so that shadow edges survive.
+const ILLUMINATION_SIGMA: f64 = 0.5;
+// Standard deviation of the spatial falloff in pixels, at a strength of 1.
+const SPATIAL_SIGMA: f64 = 2.0;
+
+// Smooths the noise out of `film`'s image, more the higher `strength` is. The film needs the
+// albedo, normal and depth AOVs; without them it is left alone.
+pub fn denoise(film: &mut Film, strength: f64) {
+    if strength <= 0.0 {
+        return;
+    }
+    let (Some(albedo), Some(normal), Some(depth)) = (film.aov_pixels("albedo"), film.aov_pixels("normal"), film.aov_pixels("depth")) else { return };
+    let width = film.width() as usize;
+    let height = film.height() as usize;
+
+    // Albedo channels near zero, as on lights and black surfaces, are left as they are.
+    let demodulate = |albedo: &Color| Color::new(factor(albedo.x()), factor(albedo.y()), factor(albedo.z()));
+    let illumination: Vec<Color> = film.pixels().iter().zip(&albedo).map(|(color, albedo)| divide(color, &demodulate(albedo))).collect();
+    let log_illumination: Vec<Color> = illumination.iter().map(|color| Color::new(log(color.x()), log(color.y()), log(color.z()))).collect();
+
+    let spatial_sigma = SPATIAL_SIGMA * strength;
+    let radius = (2.0 * spatial_sigma).ceil() as isize;
+    let falloff = |distance_sq: f64, sigma: f64| -distance_sq / (2.0 * sigma * sigma * strength * strength);
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in 0..height {
+        for col in 0..width {
+            let index = row * width + col;
+            let mut sum = Color::new(0.0, 0.0, 0.0);
+            let mut weight_sum = 0.0;
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let (Some(other_row), Some(other_col)) = (row.checked_add_signed(dy), col.checked_add_signed(dx)) else { continue };
+                    if other_row >= height || other_col >= width {
+                        continue;
+                    }
+                    let other = other_row * width + other_col;
+                    let exponent = -((dx * dx + dy * dy) as f64) / (2.0 * spatial_sigma * spatial_sigma)
+                        + falloff((albedo[index] - albedo[other]).len_sq(), ALBEDO_SIGMA)
+                        + falloff((normal[index] - normal[other]).len_sq(), NORMAL_SIGMA)
+                        + falloff((depth[index] - depth[other]).len_sq(), DEPTH_SIGMA)
+                        + falloff((log_illumination[index] - log_illumination[other]).len_sq(), ILLUMINATION_SIGMA);
+                    let weight = exponent.exp();
+                    sum += weight * illumination[other];
+                    weight_sum += weight;
+                }
+            }
+            // The pixel itself always has weight 1, so the sum is never zero.
+            pixels.push(sum / weight_sum * demodulate(&albedo[index]));
+        }
+    }
+    film.set_pixels(&pixels);
+}
+
+fn factor(albedo: f64) -> f64 {
+    if albedo < 0.01 { 1.0 } else { albedo }
+}
+
+fn divide(a: &Color, b: &Color) -> Color {
+    Color::new(a.x() / b.x(), a.y() / b.y(), a.z() / b.z())
+}
+
+fn log(value: f64) -> f64 {
+    value.max(0.0).ln_1p()
+}
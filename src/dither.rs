@@ -0,0 +1,120 @@
+// Dithering: noise of about one quantization step added to each channel before it is rounded
+// to an integer. Smooth gradients, like the sky, otherwise break into visible bands where
+// they cross from one level to the next. The noise turns the bands into fine grain that the
+// eye averages out.
+
+use crate::util::random;
+
+use clap::ValueEnum;
+
+use rand::SeedableRng;
+use rand::rngs::SmallRng;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Dither {
+    None,
+    // White noise with a triangular distribution over (-1, 1) steps, which makes the error
+    // independent of the signal.
+    Tpdf,
+    // A tiled blue noise texture, remapped to the same triangular distribution. Its grain has
+    // no low frequencies, so it is less visible than white noise.
+    BlueNoise,
+}
+
+// Side of the blue noise texture, which tiles the image.
+const BLUE_NOISE_SIZE: usize = 64;
+
+// Noise to add to each channel of each pixel of a `width` by `height` image, row by row, in
+// quantization steps. The same image always gets the same noise.
+pub fn noise(dither: Dither, width: u32, height: u32) -> Vec<[f64; 3]> {
+    let (width, height) = (width as usize, height as usize);
+    match dither {
+        Dither::None => vec![[0.0; 3]; width * height],
+        Dither::Tpdf => {
+            let mut rng = SmallRng::seed_from_u64(0);
+            let mut triangular = || random(0.0, 1.0, &mut rng) + random(0.0, 1.0, &mut rng) - 1.0;
+            (0..width * height).map(|_| [triangular(), triangular(), triangular()]).collect()
+        },
+        Dither::BlueNoise => {
+            let texture = blue_noise();
+            let at = |row: usize, col: usize| triangular(texture[(row % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + col % BLUE_NOISE_SIZE]);
+            // Each channel reads the texture at an offset, so that their noise is unrelated.
+            let offset = BLUE_NOISE_SIZE / 2;
+            (0..height).flat_map(|row| (0..width).map(move |col| (row, col)))
+                .map(|(row, col)| [at(row, col), at(row + offset, col), at(row, col + offset)])
+                .collect()
+        },
+    }
+}
+
+// Remaps `u`, uniform over [0, 1), to a triangular distribution over (-1, 1).
+fn triangular(u: f64) -> f64 {
+    if u < 0.5 { (2.0 * u).sqrt() - 1.0 } else { 1.0 - (2.0 - 2.0 * u).sqrt() }
+}
+
+// A blue noise texture of values uniform over [0, 1), made by Ulichney's void and cluster
+// method. Pixels are ranked in the order they are added to a pattern that is kept as evenly
+// spread as possible: each goes in the largest void, the spot farthest from the pixels
+// already in. The texture wraps around, so it tiles without seams.
+fn blue_noise() -> Vec<f64> {
+    const SIGMA: f64 = 1.5;
+    let size = BLUE_NOISE_SIZE;
+    let count = size * size;
+
+    // How much a pixel crowds another at each offset, wrapping around.
+    let kernel: Vec<f64> = (0..count).map(|index| {
+        let wrap = |d: usize| d.min(size - d) as f64;
+        let (dy, dx) = (wrap(index / size), wrap(index % size));
+        (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+    }).collect();
+    let offset = |a: usize, b: usize| ((a / size + size - b / size) % size) * size + (a % size + size - b % size) % size;
+
+    // Energy is how crowded each pixel is by the pixels in the pattern.
+    let mut pattern = vec![false; count];
+    let mut energy = vec![0.0; count];
+    let toggle = |pattern: &mut Vec<bool>, energy: &mut Vec<f64>, pixel: usize| {
+        pattern[pixel] = !pattern[pixel];
+        let sign = if pattern[pixel] { 1.0 } else { -1.0 };
+        for (other, value) in energy.iter_mut().enumerate() {
+            *value += sign * kernel[offset(other, pixel)];
+        }
+    };
+    let tightest_cluster = |pattern: &[bool], energy: &[f64]| (0..count).filter(|&pixel| pattern[pixel]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap();
+    let largest_void = |pattern: &[bool], energy: &[f64]| (0..count).filter(|&pixel| !pattern[pixel]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap();
+
+    // Start from a tenth of the pixels at random, then move the most crowded one to the
+    // largest void until that no longer changes anything.
+    let mut rng = SmallRng::seed_from_u64(0);
+    let initial = count / 10;
+    while pattern.iter().filter(|&&set| set).count() < initial {
+        let pixel = (random(0.0, 1.0, &mut rng) * count as f64) as usize % count;
+        if !pattern[pixel] {
+            toggle(&mut pattern, &mut energy, pixel);
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, cluster);
+        let void = largest_void(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    // The initial pixels rank below the rest, in the reverse of the order they come out by
+    // most crowded. The others rank in the order they go into the largest void.
+    let mut rank = vec![0; count];
+    let (mut initial_pattern, mut initial_energy) = (pattern.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = tightest_cluster(&initial_pattern, &initial_energy);
+        toggle(&mut initial_pattern, &mut initial_energy, cluster);
+        rank[cluster] = r;
+    }
+    for r in initial..count {
+        let void = largest_void(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, void);
+        rank[void] = r;
+    }
+    rank.iter().map(|&r| (r as f64 + 0.5) / count as f64).collect()
+}
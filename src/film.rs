@@ -0,0 +1,142 @@
+// The image being rendered. Each pixel is the average of the samples taken near it, weighted
+// by the reconstruction filter (see filter.rs), plus any light that paths starting at the
+// lights splat onto it. Any AOVs (see aov.rs) are kept alongside, with the same weights.
+
+use crate::color::Color;
+use crate::filter::{Filter, FilterKind};
+
+// Samples weighted less than this, as near where a filter's negative lobes cross zero, are
+// dropped. A pixel whose weights add up to less than this per sample is black, since
+// dividing by a sum that positive and negative weights nearly cancel out in turns noise
+// into fireflies.
+const MIN_WEIGHT: f64 = 1e-3;
+
+pub struct Film {
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+    filter: Filter,
+    samples: Vec<Color>,  // Weighted sum of the samples taken for each pixel, row by row
+    weights: Vec<f64>,  // Sum of those weights
+    splats: Vec<Color>,  // Sum of the light splatted onto each pixel
+    aovs: Vec<Aov>,
+}
+
+struct Aov {
+    name: String,
+    samples: Vec<Color>,  // Weighted sum of the samples taken for each pixel
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, samples_per_pixel: u32) -> Self {
+        let pixel_count = (width as usize) * (height as usize);
+        Self {
+            width: width,
+            height: height,
+            samples_per_pixel: samples_per_pixel.max(1),
+            filter: Filter::new(FilterKind::Box, None),
+            samples: vec![Color::new(0.0, 0.0, 0.0); pixel_count],
+            weights: vec![0.0; pixel_count],
+            splats: vec![Color::new(0.0, 0.0, 0.0); pixel_count],
+            aovs: vec![],
+        }
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn add_aov(&mut self, name: &str) {
+        self.aovs.push(Aov {
+            name: name.to_string(),
+            samples: vec![Color::new(0.0, 0.0, 0.0); self.samples.len()],
+        });
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Names and final linear colors of the AOVs, in the order they were added.
+    pub fn aovs(&self) -> impl Iterator<Item = (&str, Vec<Color>)> {
+        self.aovs.iter().map(|aov| (aov.name.as_str(), (0..aov.samples.len()).map(|index| self.average(&aov.samples, index)).collect()))
+    }
+
+    // Adds a sample taken at the raster position (x, y), measured in pixels from the top left
+    // corner of the image, to every pixel within the filter's radius. `aovs` holds the
+    // sample's value for each AOV, in the order they were added.
+    pub fn add_sample(&mut self, x: f64, y: f64, color: &Color, aovs: &[Color]) {
+        let radius = self.filter.radius();
+        // Pixel centers are at half-integer positions.
+        let first_col = (x - 0.5 - radius).floor().max(0.0) as u32;
+        let last_col = ((x - 0.5 + radius).ceil().max(0.0) as u32).min(self.width.saturating_sub(1));
+        let first_row = (y - 0.5 - radius).floor().max(0.0) as u32;
+        let last_row = ((y - 0.5 + radius).ceil().max(0.0) as u32).min(self.height.saturating_sub(1));
+        for row in first_row..=last_row {
+            for col in first_col..=last_col {
+                let weight = self.filter.evaluate(x - (col as f64 + 0.5), y - (row as f64 + 0.5));
+                if weight.abs() < MIN_WEIGHT {
+                    continue;
+                }
+                let index = self.index(row, col);
+                self.samples[index] += weight * *color;
+                self.weights[index] += weight;
+                for (aov, value) in self.aovs.iter_mut().zip(aovs) {
+                    aov.samples[index] += weight * *value;
+                }
+            }
+        }
+    }
+
+    // Adds light arriving at the raster position (x, y), measured in pixels from the top
+    // left corner of the image. Splats are averaged over the same number of samples as the
+    // pixels, since every camera sample may come with a light path.
+    pub fn add_splat(&mut self, x: f64, y: f64, color: &Color) {
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return;
+        }
+        let index = self.index(y as u32, x as u32);
+        self.splats[index] += *color;
+    }
+
+    // Final linear color of the pixel at `index`, counting row by row.
+    fn pixel_at(&self, index: usize) -> Color {
+        self.average(&self.samples, index) + self.splats[index] / (self.samples_per_pixel as f64)
+    }
+
+    // Weighted average of the samples in `samples`, the image's or an AOV's, at `index`.
+    // Filters with negative lobes can leave a pixel with next to no weight, and it is black.
+    fn average(&self, samples: &[Color], index: usize) -> Color {
+        let weight = self.weights[index];
+        if weight > MIN_WEIGHT * self.samples_per_pixel as f64 { samples[index] / weight } else { Color::new(0.0, 0.0, 0.0) }
+    }
+
+    // Final linear colors of all pixels, row by row.
+    pub fn pixels(&self) -> Vec<Color> {
+        (0..self.samples.len()).map(|index| self.pixel_at(index)).collect()
+    }
+
+    // Final linear colors of the AOV called `name`, row by row, if there is one.
+    pub fn aov_pixels(&self, name: &str) -> Option<Vec<Color>> {
+        let aov = self.aovs.iter().find(|aov| aov.name == name)?;
+        Some((0..aov.samples.len()).map(|index| self.average(&aov.samples, index)).collect())
+    }
+
+    // Replaces the image with `pixels`, final linear colors row by row, as after a post
+    // process. The AOVs are kept.
+    pub fn set_pixels(&mut self, pixels: &[Color]) {
+        for (index, pixel) in pixels.iter().enumerate() {
+            self.samples[index] = self.weights[index] * *pixel;
+            self.splats[index] = Color::new(0.0, 0.0, 0.0);
+        }
+    }
+
+    fn index(&self, row: u32, col: u32) -> usize {
+        (row as usize) * (self.width as usize) + (col as usize)
+    }
+}
@@ -0,0 +1,92 @@
+// Pixel reconstruction filters. A sample taken at some point on the image counts towards
+// every pixel whose center is within the filter's radius of it, weighted by the filter, and
+// each pixel is the weighted average of its samples. Wider filters trade sharpness for less
+// aliasing and noise. All of them are separable: the weight is the product of a 1D filter
+// along x and along y.
+
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum FilterKind {
+    // Weight 1 within the radius. With the default radius of half a pixel, each sample only
+    // counts towards the pixel it was taken in.
+    Box,
+    // Falls off linearly to 0 at the radius.
+    Tent,
+    // A Gaussian with a standard deviation of a third of the radius, shifted down to reach 0
+    // there.
+    Gaussian,
+    // The Mitchell-Netravali cubic with B = C = 1/3. Its negative lobes keep edges sharp.
+    Mitchell,
+    // The Blackman-Harris window, smooth like the Gaussian but falling off faster.
+    BlackmanHarris,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Filter {
+    kind: FilterKind,
+    radius: f64,  // In pixels
+}
+
+impl Filter {
+    // A filter of `kind`, with its usual radius unless `radius` is given.
+    pub fn new(kind: FilterKind, radius: Option<f64>) -> Self {
+        let default_radius = match kind {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell | FilterKind::BlackmanHarris => 2.0,
+        };
+        Self {
+            kind: kind,
+            radius: radius.unwrap_or(default_radius),
+        }
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    // Weight of a sample `dx` and `dy` pixels from a pixel's center.
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, d: f64) -> f64 {
+        let r = self.radius;
+        // Half open, so that a box filter with radius 0.5 puts a sample on the border between
+        // two pixels in only one of them.
+        if !(-r..r).contains(&d) {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - d.abs() / r,
+            FilterKind::Gaussian => {
+                let sigma = r / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(d) - gaussian(r)
+            },
+            FilterKind::Mitchell => mitchell(2.0 * d / r),
+            FilterKind::BlackmanHarris => {
+                let x = std::f64::consts::PI * d / r;
+                0.35875 + 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos() + 0.01168 * (3.0 * x).cos()
+            },
+        }
+    }
+}
+
+// The Mitchell-Netravali cubic, which is 0 from |x| = 2 on.
+fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1.0 / 3.0;
+    const C: f64 = 1.0 / 3.0;
+    let x = x.abs();
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * B - 6.0 * C) * x * x * x + (-18.0 + 12.0 * B + 6.0 * C) * x * x + (6.0 - 2.0 * B)
+    } else if x < 2.0 {
+        (-B - 6.0 * C) * x * x * x + (6.0 * B + 30.0 * C) * x * x + (-12.0 * B - 48.0 * C) * x + (8.0 * B + 24.0 * C)
+    } else {
+        0.0
+    };
+    value / 6.0
+}
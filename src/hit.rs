@@ -8,15 +8,29 @@ use crate::vec3::Vec3;
 
 use std::rc::Rc;
 
+#[derive(Clone)]
 pub struct HitRecord {
     point: Vec3,
-    normal: Vec3,
+    normal: Vec3,  // Shading normal. Materials should use this one.
+    geometric_normal: Vec3,  // Normal of the actual surface, on the same side as `normal`
     t: f64,
 
+    // Surface coordinates of the hit point, in [0, 1]. Used for texture lookups.
+    u: f64,
+    v: f64,
+
+    // Partial derivatives of the hit point with respect to u and v. They span the tangent
+    // plane and orient tangent-space normal maps. Zero when the object doesn't provide them.
+    dpdu: Vec3,
+    dpdv: Vec3,
+
     // `true` only when the ray originates from outside the object.
     front_face: bool,
 
     material: Rc<dyn Material>,
+
+    // Number of the scene light the hit object is, if it is one.
+    light: Option<usize>,
 }
 
 impl HitRecord {
@@ -24,20 +38,58 @@ impl HitRecord {
     // based on the given ray and outward normal.
     pub fn new(point: &Vec3, ray: &Ray, t: f64, outward_normal: &Vec3, material: Rc<dyn Material>) -> Self {
         let front_face = ray.dir().dot(outward_normal) < 0.0;
+        let normal = if front_face { *outward_normal } else { -*outward_normal };
         Self {
             point: *point,
             t: t,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(0.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 0.0, 0.0),
             front_face: front_face,
-            normal: if front_face { *outward_normal } else { -*outward_normal },
+            normal: normal,
+            geometric_normal: normal,
             material: material,
+            light: None,
         }
     }
 
+    pub fn with_uv(mut self, u: f64, v: f64) -> Self {
+        self.u = u;
+        self.v = v;
+        self
+    }
+
+    pub fn with_tangents(mut self, dpdu: Vec3, dpdv: Vec3) -> Self {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self
+    }
+
+    pub fn with_light(mut self, light: Option<usize>) -> Self {
+        self.light = light;
+        self
+    }
+
+    // Replaces the shading normal, keeping it on the same side as the geometric normal.
+    // Assumes `normal` is a unit vector.
+    pub fn with_shading_normal(&self, normal: &Vec3) -> Self {
+        let mut record = self.clone();
+        record.normal = if normal.dot(&self.geometric_normal) < 0.0 { -*normal } else { *normal };
+        record
+    }
+
     pub fn point(&self) -> &Vec3 { &self.point }
     pub fn normal(&self) -> &Vec3 { &self.normal }
+    pub fn geometric_normal(&self) -> &Vec3 { &self.geometric_normal }
     pub fn t(&self) -> f64 { self.t }
+    pub fn u(&self) -> f64 { self.u }
+    pub fn v(&self) -> f64 { self.v }
+    pub fn dpdu(&self) -> &Vec3 { &self.dpdu }
+    pub fn dpdv(&self) -> &Vec3 { &self.dpdv }
     pub fn front_face(&self) -> bool { self.front_face }
     pub fn material(&self) -> Rc<dyn Material> { self.material.clone() }
+    pub fn light(&self) -> Option<usize> { self.light }
 }
 
 pub trait Hit {
@@ -19,21 +19,23 @@ impl HittableList {
     pub fn add(&mut self, object: Rc<dyn Hit>) {
         self.objects.push(object);
     }
-}
 
-impl Hit for HittableList {
-    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
-        let mut hit_result: Option<HitRecord> = None;
+    // Like `hit`, but also returns the index of the object hit, in the order they were added.
+    pub fn hit_object(&self, ray: &Ray, ray_t: &Interval) -> Option<(usize, HitRecord)> {
+        let mut hit_result: Option<(usize, HitRecord)> = None;
         let mut closest_so_far = ray_t.max();
-        for object in self.objects.iter() {
-            match object.hit(ray, &Interval::new(ray_t.min(), closest_so_far)) {
-                Some(hit) => {
-                    closest_so_far = hit.t();
-                    hit_result = Some(hit);
-                },
-                None => (),
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Some(hit) = object.hit(ray, &Interval::new(ray_t.min(), closest_so_far)) {
+                closest_so_far = hit.t();
+                hit_result = Some((index, hit));
             }
         }
         hit_result
     }
 }
+
+impl Hit for HittableList {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        self.hit_object(ray, ray_t).map(|(_, hit)| hit)
+    }
+}
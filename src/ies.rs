@@ -0,0 +1,231 @@
+// Photometric profiles of real light fixtures, read from IESNA LM-63 (.ies) files.
+//
+// A profile tabulates candela over a grid of vertical angles, measured from the fixture's
+// nadir (straight down), and horizontal angles around it. Only type C photometry is
+// supported, which is what nearly all architectural fixtures use. Values are scaled so the
+// brightest direction is 1, leaving the light's own intensity to set the brightness.
+
+use crate::onb::Onb;
+use crate::vec3::Vec3;
+
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,  // Degrees from the nadir, ascending
+    horizontal_angles: Vec<f64>,  // Degrees around the nadir, ascending
+    candela: Vec<Vec<f64>>,  // One row of vertical samples per horizontal angle
+}
+
+impl IesProfile {
+    pub fn parse(text: &str) -> Result<Self, &'static str> {
+        let mut lines = text.lines();
+
+        // Header keywords run up to the TILT line. Tilt data only matters for lamps that
+        // change output with the fixture's inclination, and is skipped.
+        let tilt = loop {
+            let line = lines.next().ok_or("IES file has no TILT line")?.trim();
+            if let Some(tilt) = line.strip_prefix("TILT=") {
+                break tilt.trim().to_string();
+            }
+        };
+        let mut numbers = lines.flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ',')).filter(|s| !s.is_empty()).map(|s| s.parse::<f64>());
+        let mut next = || numbers.next().ok_or("IES file ends early")?.map_err(|_| "IES file has a malformed number");
+        if tilt == "INCLUDE" {
+            next()?;  // Lamp to luminaire geometry
+            let count = count(next()?)?;
+            for _ in 0..count.checked_mul(2).ok_or("IES file has too many tilt angles")? {
+                next()?;
+            }
+        }
+
+        let _lamp_count = next()?;
+        let _lumens_per_lamp = next()?;
+        let _candela_multiplier = next()?;
+        let vertical_count = count(next()?)?;
+        let horizontal_count = count(next()?)?;
+        let photometric_type = next()?;
+        for _ in 0..4 {
+            next()?;  // Units and luminous opening dimensions
+        }
+        for _ in 0..3 {
+            next()?;  // Ballast factor, a reserved field and input watts
+        }
+        if photometric_type != 1.0 {
+            return Err("Only type C IES photometry is supported");
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err("IES file has no candela values");
+        }
+
+        let mut read = |count: usize| (0..count).map(|_| next()).collect::<Result<Vec<f64>, _>>();
+        let vertical_angles = read(vertical_count)?;
+        let mut horizontal_angles = read(horizontal_count)?;
+        let mut candela = (0..horizontal_count).map(|_| read(vertical_count)).collect::<Result<Vec<_>, _>>()?;
+        let ascending = |angles: &[f64]| angles.windows(2).all(|pair| pair[0] < pair[1]);
+        if !ascending(&vertical_angles) || !ascending(&horizontal_angles) {
+            return Err("IES angles must be in ascending order");
+        }
+
+        // A full circle of horizontal angles that stops short of 360 wraps back to the first.
+        let first = horizontal_angles[0];
+        let last = horizontal_angles[horizontal_count - 1];
+        if last > 180.0 && last < first + 360.0 && !(first == 90.0 && last == 270.0) {
+            horizontal_angles.push(first + 360.0);
+            candela.push(candela[0].clone());
+        }
+
+        let peak = candela.iter().flatten().fold(0.0_f64, |peak, &value| peak.max(value));
+        if peak <= 0.0 {
+            return Err("IES file has no light in any direction");
+        }
+        for value in candela.iter_mut().flatten() {
+            *value = value.max(0.0) / peak;
+        }
+
+        Ok(Self {
+            vertical_angles: vertical_angles,
+            horizontal_angles: horizontal_angles,
+            candela: candela,
+        })
+    }
+
+    // Relative intensity towards `direction`, for a fixture whose nadir points along
+    // `nadir`. Horizontal angle 0 lies along the u axis of the basis built around the nadir.
+    pub fn intensity(&self, direction: &Vec3, nadir: &Vec3) -> f64 {
+        let local = Onb::new(nadir).to_local(&direction.unit_vec());
+        let vertical = local.z().clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = local.y().atan2(local.x()).to_degrees().rem_euclid(360.0);
+
+        let Some((i, s)) = locate(&self.vertical_angles, vertical) else { return 0.0 };
+        let Some((j, t)) = locate(&self.horizontal_angles, self.fold_horizontal(horizontal)) else { return 0.0 };
+        let at = |j: usize, i: usize| self.candela[j.min(self.candela.len() - 1)][i.min(self.vertical_angles.len() - 1)];
+        let near = (1.0 - s) * at(j, i) + s * at(j, i + 1);
+        let far = (1.0 - s) * at(j + 1, i) + s * at(j + 1, i + 1);
+        (1.0 - t) * near + t * far
+    }
+
+    // Maps a horizontal angle in [0, 360) into the range the file covers, using the
+    // symmetry implied by that range.
+    fn fold_horizontal(&self, angle: f64) -> f64 {
+        let first = self.horizontal_angles[0];
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        if self.horizontal_angles.len() == 1 {
+            // Rotationally symmetric
+            first
+        } else if first == 90.0 && last == 270.0 {
+            // Symmetric about the 90-270 plane
+            if angle < 90.0 { 180.0 - angle } else if angle > 270.0 { 540.0 - angle } else { angle }
+        } else if last <= 90.0 {
+            // Symmetric in each quadrant
+            let angle = if angle > 180.0 { 360.0 - angle } else { angle };
+            if angle > 90.0 { 180.0 - angle } else { angle }
+        } else if last <= 180.0 {
+            // Symmetric about the 0-180 plane
+            if angle > 180.0 { 360.0 - angle } else { angle }
+        } else if angle < first {
+            angle + 360.0
+        } else {
+            angle
+        }
+    }
+}
+
+// The most angles of one kind a file may list. Real fixtures have a few hundred at most, and
+// the cap keeps corrupt files from asking for more than fits in memory.
+const MAX_COUNT: f64 = 100_000.0;
+
+// Reads a count of values, which must be a whole number no larger than MAX_COUNT.
+fn count(value: f64) -> Result<usize, &'static str> {
+    if !(0.0..=MAX_COUNT).contains(&value) || value.fract() != 0.0 {
+        return Err("IES file has an invalid count");
+    }
+    Ok(value as usize)
+}
+
+// Index of the sample at or before `x` and how far `x` is towards the next one, or None
+// if `x` is outside the samples.
+fn locate(angles: &[f64], x: f64) -> Option<(usize, f64)> {
+    let last = angles.len() - 1;
+    if x < angles[0] || x > angles[last] {
+        return None;
+    }
+    if last == 0 {
+        return Some((0, 0.0));
+    }
+    let i = (angles.partition_point(|&angle| angle <= x).max(1) - 1).min(last - 1);
+    let t = (x - angles[i]) / (angles[i + 1] - angles[i]);
+    Some((i, t.clamp(0.0, 1.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A rotationally symmetric fixture with its angles and candela split across lines.
+    const FIXTURE: &str = "IESNA:LM-63-2002
+[TEST] Test fixture
+[MANUFAC] Nobody
+TILT=NONE
+1 1000 1 3 1 1 2 0 0 0
+1 1 100
+0 45
+90
+0
+200
+100 0
+";
+
+    fn towards(vertical_degrees: f64) -> Vec3 {
+        let angle = vertical_degrees.to_radians();
+        Vec3::new(angle.sin(), -angle.cos(), 0.0)
+    }
+
+    #[test]
+    fn parses_values_split_across_lines() {
+        let profile = IesProfile::parse(FIXTURE).unwrap();
+        assert_eq!(profile.vertical_angles, vec![0.0, 45.0, 90.0]);
+        assert_eq!(profile.horizontal_angles, vec![0.0]);
+        assert_eq!(profile.candela, vec![vec![1.0, 0.5, 0.0]]);
+    }
+
+    #[test]
+    fn interpolates_between_angles() {
+        let profile = IesProfile::parse(FIXTURE).unwrap();
+        let nadir = Vec3::new(0.0, -1.0, 0.0);
+        assert!((profile.intensity(&towards(0.0), &nadir) - 1.0).abs() < 1e-9);
+        assert!((profile.intensity(&towards(22.5), &nadir) - 0.75).abs() < 1e-9);
+        assert!((profile.intensity(&towards(45.0), &nadir) - 0.5).abs() < 1e-9);
+        assert_eq!(profile.intensity(&towards(135.0), &nadir), 0.0);
+    }
+
+    #[test]
+    fn skips_included_tilt_data() {
+        let fixture = FIXTURE.replace("TILT=NONE", "TILT=INCLUDE\n1\n2\n0 90\n1 1");
+        let profile = IesProfile::parse(&fixture).unwrap();
+        assert_eq!(profile.candela, vec![vec![1.0, 0.5, 0.0]]);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let end = FIXTURE.find("100 0").unwrap();
+        assert!(IesProfile::parse(&FIXTURE[..end]).is_err());
+        assert!(IesProfile::parse("IESNA:LM-63-2002\n[TEST] No tilt\n").is_err());
+        assert!(IesProfile::parse("").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(IesProfile::parse(&FIXTURE.replace("100 0", "100 zero")).is_err());
+        assert!(IesProfile::parse(&FIXTURE.replace("1 1000 1 3 1 1", "1 1000 1 3 1 3")).is_err());
+        assert!(IesProfile::parse(&FIXTURE.replace("0 45\n90", "0 90\n45")).is_err());
+        assert!(IesProfile::parse(&FIXTURE.replace("200\n100 0", "0\n0 0")).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_counts() {
+        let tilt = |count: &str| FIXTURE.replace("TILT=NONE", &format!("TILT=INCLUDE\n1\n{count}\n0 90\n1 1"));
+        assert!(IesProfile::parse(&tilt("1e300")).is_err());
+        assert!(IesProfile::parse(&tilt("-1")).is_err());
+        for vertical_count in ["-3", "3.5", "NaN", "inf", "1e19"] {
+            assert!(IesProfile::parse(&FIXTURE.replace("1 1000 1 3 1", &format!("1 1000 1 {vertical_count} 1"))).is_err());
+        }
+    }
+}
@@ -0,0 +1,635 @@
+// Lights the camera samples directly with shadow rays. Punctual and directional lights
+// aren't part of the geometry and give noise-free direct lighting; sphere lights are
+// emissive objects in the world too.
+
+use crate::color::Color;
+use crate::hit::{Hit, HitRecord};
+use crate::ies::IesProfile;
+use crate::interval::Interval;
+use crate::material::DiffuseLight;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::util::random;
+use crate::vec3::Vec3;
+
+use std::cell::{Cell, OnceCell};
+use std::rc::Rc;
+
+pub struct LightSample {
+    direction: Vec3,  // Unit vector from the lit point towards the light
+    distance: f64,  // Distance to the light along `direction`, or infinity
+
+    radiance: Color,  // Light arriving at the point, before any shadowing
+
+    // Probability density of the direction per unit solid angle, including the chance of
+    // picking the light. Lights that shine from a single point or direction use 1.
+    pdf: f64,
+}
+
+impl LightSample {
+    pub fn direction(&self) -> &Vec3 { &self.direction }
+    pub fn distance(&self) -> f64 { self.distance }
+    pub fn radiance(&self) -> &Color { &self.radiance }
+    pub fn pdf(&self) -> f64 { self.pdf }
+}
+
+// Light leaving a light, for starting paths at lights rather than at the camera.
+pub struct Emission {
+    ray: Ray,  // From the point on the light, in the direction the light leaves
+    radiance: Color,  // Light carried along the ray
+    pdf_position: f64,  // Density of the point per unit area, or 1 for point-like lights
+    pdf_direction: f64,  // Density of the direction per unit solid angle
+}
+
+impl Emission {
+    pub fn ray(&self) -> &Ray { &self.ray }
+    pub fn radiance(&self) -> &Color { &self.radiance }
+    pub fn pdf_position(&self) -> f64 { self.pdf_position }
+    pub fn pdf_direction(&self) -> f64 { self.pdf_direction }
+}
+
+pub trait Light {
+    // Returns the light arriving at `point`, or None if none of it can get there.
+    fn sample(&self, point: &Vec3, rng: &mut dyn rand::RngCore) -> Option<LightSample>;
+
+    // Roughly the total power given off, averaged over the color channels, used to decide
+    // how often to sample the light. None for lights at infinity, which are always sampled.
+    fn power(&self) -> Option<f64>;
+
+    // Picks a point on the light and a direction for light to leave it in. Lights at
+    // infinity don't support this.
+    fn sample_emission(&self, _rng: &mut dyn rand::RngCore) -> Option<Emission> { None }
+
+    // The densities `sample_emission` would pick `point` and `direction` with.
+    fn pdf_emission(&self, _point: &Vec3, _direction: &Vec3) -> (f64, f64) { (0.0, 0.0) }
+
+    // Normal of the light's surface at `point`, or None for lights without a surface.
+    fn normal(&self, _point: &Vec3) -> Option<Vec3> { None }
+
+    // Tells the light its number in the LightList it was added to. Lights that are also
+    // objects in the world tag their hits with it, so the light can be found from a hit.
+    fn set_index(&self, _index: usize) {}
+}
+
+fn average(color: &Color) -> f64 {
+    (color.x() + color.y() + color.z()) / 3.0
+}
+
+// Emits equally in all directions from a single point, unless given a profile.
+pub struct PointLight {
+    position: Vec3,
+    intensity: Color,  // Power per unit solid angle
+    profile: Option<Rc<IesProfile>>,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Color) -> Self {
+        Self {
+            position: position,
+            intensity: intensity,
+            profile: None,
+        }
+    }
+
+    // Shapes the light like the fixture in `profile`, hanging with its nadir straight
+    // down. `intensity` then applies to the profile's brightest direction.
+    pub fn with_profile(mut self, profile: Rc<IesProfile>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: &Vec3, _: &mut dyn rand::RngCore) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance_sq = to_light.len_sq();
+        if distance_sq <= 0.0 {
+            return None;
+        }
+        let direction = to_light / distance_sq.sqrt();
+        let scale = match &self.profile {
+            Some(profile) => profile.intensity(&-direction, &Vec3::new(0.0, -1.0, 0.0)),
+            None => 1.0,
+        };
+        if scale <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction: direction,
+            distance: distance_sq.sqrt(),
+            radiance: scale * self.intensity / distance_sq,
+            pdf: 1.0,
+        })
+    }
+
+    fn power(&self) -> Option<f64> {
+        Some(4.0 * std::f64::consts::PI * average(&self.intensity))
+    }
+
+    fn sample_emission(&self, rng: &mut dyn rand::RngCore) -> Option<Emission> {
+        let direction = Vec3::uniform_random_unit_vec(rng);
+        let scale = match &self.profile {
+            Some(profile) => profile.intensity(&direction, &Vec3::new(0.0, -1.0, 0.0)),
+            None => 1.0,
+        };
+        Some(Emission {
+            ray: Ray::new(self.position, direction),
+            radiance: scale * self.intensity,
+            pdf_position: 1.0,
+            pdf_direction: 1.0 / (4.0 * std::f64::consts::PI),
+        })
+    }
+
+    fn pdf_emission(&self, _: &Vec3, _: &Vec3) -> (f64, f64) {
+        (1.0, 1.0 / (4.0 * std::f64::consts::PI))
+    }
+}
+
+// A point light restricted to a cone. Full intensity inside `inner_angle_degrees` of the
+// axis, fading smoothly to nothing at `outer_angle_degrees`.
+pub struct SpotLight {
+    position: Vec3,
+    axis: Vec3,  // Unit vector the spot points along
+    intensity: Color,  // Power per unit solid angle along the axis
+    cos_inner: f64,
+    cos_outer: f64,
+    profile: Option<Rc<IesProfile>>,
+}
+
+impl SpotLight {
+    pub fn new(position: Vec3, look_at: Vec3, intensity: Color, inner_angle_degrees: f64, outer_angle_degrees: f64) -> Self {
+        let outer = outer_angle_degrees.clamp(0.0, 180.0);
+        let inner = inner_angle_degrees.clamp(0.0, outer);
+        Self {
+            position: position,
+            axis: (look_at - position).unit_vec(),
+            intensity: intensity,
+            cos_inner: inner.to_radians().cos(),
+            cos_outer: outer.to_radians().cos(),
+            profile: None,
+        }
+    }
+
+    // Shapes the light like the fixture in `profile`, with its nadir along the spot's
+    // axis. The cone falloff still applies on top.
+    pub fn with_profile(mut self, profile: Rc<IesProfile>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    // Smoothstep between the outer and inner cone.
+    fn falloff(&self, cos_angle: f64) -> f64 {
+        if cos_angle >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_angle <= self.cos_outer {
+            return 0.0;
+        }
+        let t = (cos_angle - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: &Vec3, _: &mut dyn rand::RngCore) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance_sq = to_light.len_sq();
+        if distance_sq <= 0.0 {
+            return None;
+        }
+        let direction = to_light / distance_sq.sqrt();
+        let mut falloff = self.falloff(-direction.dot(&self.axis));
+        if let Some(profile) = &self.profile {
+            falloff *= profile.intensity(&-direction, &self.axis);
+        }
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction: direction,
+            distance: distance_sq.sqrt(),
+            radiance: falloff * self.intensity / distance_sq,
+            pdf: 1.0,
+        })
+    }
+
+    // Treats the falloff as linear in the cosine.
+    fn power(&self) -> Option<f64> {
+        let solid_angle = 2.0 * std::f64::consts::PI * (1.0 - 0.5 * (self.cos_inner + self.cos_outer));
+        Some(solid_angle * average(&self.intensity))
+    }
+
+    // Picks a direction uniformly within the outer cone.
+    fn sample_emission(&self, rng: &mut dyn rand::RngCore) -> Option<Emission> {
+        let cos_theta = 1.0 - random(0.0, 1.0, rng) * (1.0 - self.cos_outer);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = random(0.0, 2.0 * std::f64::consts::PI, rng);
+        let direction = Onb::new(&self.axis).to_world(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta));
+        let mut scale = self.falloff(cos_theta);
+        if let Some(profile) = &self.profile {
+            scale *= profile.intensity(&direction, &self.axis);
+        }
+        let (pdf_position, pdf_direction) = self.pdf_emission(&self.position, &direction);
+        Some(Emission {
+            ray: Ray::new(self.position, direction),
+            radiance: scale * self.intensity,
+            pdf_position: pdf_position,
+            pdf_direction: pdf_direction,
+        })
+    }
+
+    fn pdf_emission(&self, _: &Vec3, direction: &Vec3) -> (f64, f64) {
+        if direction.unit_vec().dot(&self.axis) < self.cos_outer || self.cos_outer >= 1.0 {
+            return (1.0, 0.0);
+        }
+        (1.0, 1.0 / (2.0 * std::f64::consts::PI * (1.0 - self.cos_outer)))
+    }
+}
+
+// Parallel light from infinitely far away, like the sun.
+pub struct DirectionalLight {
+    to_light: Vec3,  // Unit vector pointing back towards the light
+    irradiance: Color,  // Power per unit area on a surface facing the light
+}
+
+impl DirectionalLight {
+    // `direction` is the way the light travels.
+    pub fn new(direction: Vec3, irradiance: Color) -> Self {
+        Self {
+            to_light: -direction.unit_vec(),
+            irradiance: irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _: &Vec3, _: &mut dyn rand::RngCore) -> Option<LightSample> {
+        Some(LightSample {
+            direction: self.to_light,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+            pdf: 1.0,
+        })
+    }
+
+    fn power(&self) -> Option<f64> {
+        None
+    }
+}
+
+// A glowing sphere, which is both an object in the world and a light. Add the same value
+// to the world and to the scene's lights.
+pub struct SphereLight {
+    sphere: Sphere,
+    center: Vec3,
+    radius: f64,
+    emission: Color,  // Radiance leaving the surface
+    index: Cell<Option<usize>>,  // Number in the scene's lights, once added
+}
+
+impl SphereLight {
+    pub fn new(center: Vec3, radius: f64, emission: Color) -> Self {
+        let radius = radius.max(0.0);
+        Self {
+            sphere: Sphere::new(center, radius, Rc::new(DiffuseLight::new(emission))),
+            center: center,
+            radius: radius,
+            emission: emission,
+            index: Cell::new(None),
+        }
+    }
+
+    // Picks a point uniformly over the surface. Points facing away from `point` give no light.
+    fn sample_area(&self, point: &Vec3, rng: &mut dyn rand::RngCore) -> Option<LightSample> {
+        let normal = Vec3::uniform_random_unit_vec(rng);
+        let to_light = self.center + self.radius * normal - *point;
+        let distance_sq = to_light.len_sq();
+        if distance_sq <= 0.0 {
+            return None;
+        }
+        let direction = to_light / distance_sq.sqrt();
+        let cos_light = -direction.dot(&normal);
+        if cos_light <= 0.0 {
+            return None;
+        }
+        let area = 4.0 * std::f64::consts::PI * self.radius * self.radius;
+        Some(LightSample {
+            direction: direction,
+            distance: distance_sq.sqrt(),
+            radiance: self.emission,
+            pdf: distance_sq / (cos_light * area),
+        })
+    }
+}
+
+impl Hit for SphereLight {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+        Some(self.sphere.hit(ray, ray_t)?.with_light(self.index.get()))
+    }
+}
+
+impl Light for SphereLight {
+    // Picks a direction uniformly within the cone of directions that hit the sphere. From
+    // inside the sphere it falls back to picking a point uniformly over the surface.
+    fn sample(&self, point: &Vec3, rng: &mut dyn rand::RngCore) -> Option<LightSample> {
+        let to_center = self.center - *point;
+        let distance_sq = to_center.len_sq();
+        let radius_sq = self.radius * self.radius;
+        if distance_sq <= radius_sq {
+            return self.sample_area(point, rng);
+        }
+
+        // 1 - cos written with the sine, which keeps its precision for small cones.
+        let sin_max_sq = radius_sq / distance_sq;
+        let cos_max = (1.0 - sin_max_sq).max(0.0).sqrt();
+        let one_minus_cos_max = sin_max_sq / (1.0 + cos_max);
+
+        let one_minus_cos = random(0.0, 1.0, rng) * one_minus_cos_max;
+        let cos_theta = 1.0 - one_minus_cos;
+        let sin_theta_sq = (one_minus_cos * (2.0 - one_minus_cos)).max(0.0);
+        let sin_theta = sin_theta_sq.sqrt();
+        let phi = random(0.0, 2.0 * std::f64::consts::PI, rng);
+        let direction = Onb::new(&to_center).to_world(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta));
+
+        // Nearer of the two intersections with the sphere along the direction.
+        let distance = distance_sq.sqrt();
+        let distance = distance * cos_theta - (radius_sq - distance_sq * sin_theta_sq).max(0.0).sqrt();
+        Some(LightSample {
+            direction: direction,
+            distance: distance,
+            radiance: self.emission,
+            pdf: 1.0 / (2.0 * std::f64::consts::PI * one_minus_cos_max),
+        })
+    }
+
+    fn power(&self) -> Option<f64> {
+        let area = 4.0 * std::f64::consts::PI * self.radius * self.radius;
+        Some(std::f64::consts::PI * area * average(&self.emission))
+    }
+
+    // Picks a point uniformly over the surface and a cosine-weighted direction from it.
+    fn sample_emission(&self, rng: &mut dyn rand::RngCore) -> Option<Emission> {
+        let normal = Vec3::uniform_random_unit_vec(rng);
+        let mut direction = normal + Vec3::uniform_random_unit_vec(rng);
+        if direction.is_near_zero() {
+            direction = normal;
+        }
+        let point = self.center + self.radius * normal;
+        let (pdf_position, pdf_direction) = self.pdf_emission(&point, &direction);
+        Some(Emission {
+            ray: Ray::new(point, direction.unit_vec()),
+            radiance: self.emission,
+            pdf_position: pdf_position,
+            pdf_direction: pdf_direction,
+        })
+    }
+
+    fn pdf_emission(&self, point: &Vec3, direction: &Vec3) -> (f64, f64) {
+        let area = 4.0 * std::f64::consts::PI * self.radius * self.radius;
+        let normal = (*point - self.center).unit_vec();
+        let cos = direction.unit_vec().dot(&normal).max(0.0);
+        (1.0 / area, cos / std::f64::consts::PI)
+    }
+
+    fn normal(&self, point: &Vec3) -> Option<Vec3> {
+        Some((*point - self.center).unit_vec())
+    }
+
+    fn set_index(&self, index: usize) {
+        self.index.set(Some(index));
+    }
+}
+
+// The scene's lights. Each shading point gets a sample from every light at infinity and
+// from one other light, picked in proportion to its power, so the noise depends on the
+// brightest lights rather than how many there are.
+// Lights are numbered in the order they were added.
+pub struct LightList {
+    lights: Vec<Rc<dyn Light>>,  // Lights with a power
+    infinite_lights: Vec<Rc<dyn Light>>,
+
+    // Over `lights`, by power. Built when first needed, once the scene has all its lights.
+    distribution: OnceCell<AliasTable>,
+
+    // Number of each light in `lights` and `infinite_lights`.
+    indices: Vec<usize>,
+    infinite_indices: Vec<usize>,
+}
+
+impl LightList {
+    pub fn new() -> Self {
+        Self {
+            lights: vec![],
+            infinite_lights: vec![],
+            distribution: OnceCell::new(),
+            indices: vec![],
+            infinite_indices: vec![],
+        }
+    }
+
+    pub fn add(&mut self, light: Rc<dyn Light>) {
+        let index = self.len();
+        light.set_index(index);
+        if light.power().is_none() {
+            self.infinite_lights.push(light);
+            self.infinite_indices.push(index);
+            return;
+        }
+        self.lights.push(light);
+        self.indices.push(index);
+        self.distribution.take();
+    }
+
+    fn distribution(&self) -> &AliasTable {
+        self.distribution.get_or_init(|| {
+            let powers: Vec<f64> = self.lights.iter().map(|light| light.power().unwrap_or(0.0)).collect();
+            AliasTable::new(&powers)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len() + self.infinite_lights.len()
+    }
+
+    // Total power of the lights that aren't at infinity.
+    pub fn power(&self) -> f64 {
+        self.lights.iter().filter_map(|light| light.power()).sum()
+    }
+
+    // Samples light arriving at `point`, returning each sample with the number of its light.
+    pub fn sample(&self, point: &Vec3, rng: &mut dyn rand::RngCore) -> Vec<(usize, LightSample)> {
+        let mut samples: Vec<(usize, LightSample)> = self.infinite_lights.iter().zip(&self.infinite_indices)
+            .filter_map(|(light, &index)| Some((index, light.sample(point, rng)?)))
+            .collect();
+        if let Some((index, probability)) = self.distribution().sample(rng)
+            && let Some(mut sample) = self.lights[index].sample(point, rng) {
+            sample.pdf *= probability;
+            samples.push((self.indices[index], sample));
+        }
+        samples
+    }
+
+    // Picks one of the lights that aren't at infinity, returning it with the probability
+    // of picking it.
+    pub fn choose(&self, rng: &mut dyn rand::RngCore) -> Option<(&dyn Light, f64)> {
+        let (index, probability) = self.distribution().sample(rng)?;
+        Some((self.lights[index].as_ref(), probability))
+    }
+
+    // The light numbered `index`, with the probability of `choose` picking it. None for
+    // lights at infinity.
+    pub fn find(&self, index: usize) -> Option<(&dyn Light, f64)> {
+        let position = self.indices.binary_search(&index).ok()?;
+        Some((self.lights[position].as_ref(), self.distribution().probability(position)))
+    }
+
+    pub fn infinite_lights(&self) -> impl Iterator<Item = &Rc<dyn Light>> {
+        self.infinite_lights.iter()
+    }
+}
+
+// Walker's alias method: picks an index in proportion to its weight in constant time.
+struct AliasTable {
+    probabilities: Vec<f64>,  // Normalized weight of each index
+    thresholds: Vec<f64>,  // Chance of keeping each bucket's own index over its alias
+    aliases: Vec<usize>,
+}
+
+impl AliasTable {
+    // Falls back to equal weights if they're all zero.
+    fn new(weights: &[f64]) -> Self {
+        let n = weights.len();
+        let weights: Vec<f64> = weights.iter().map(|&w| if w.is_finite() { w.max(0.0) } else { 0.0 }).collect();
+        let total: f64 = weights.iter().sum();
+        let probabilities: Vec<f64> = if total > 0.0 {
+            weights.iter().map(|w| w / total).collect()
+        } else {
+            vec![1.0 / n as f64; n]
+        };
+
+        // Vose's construction: pair each underfull bucket with an overfull one.
+        let mut thresholds: Vec<f64> = probabilities.iter().map(|p| p * n as f64).collect();
+        let mut aliases: Vec<usize> = (0..n).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) = (0..n).partition(|&i| thresholds[i] < 1.0);
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            aliases[s] = l;
+            thresholds[l] -= 1.0 - thresholds[s];
+            if thresholds[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // Whatever is left is full up to rounding.
+        for i in small.into_iter().chain(large) {
+            thresholds[i] = 1.0;
+        }
+
+        Self {
+            probabilities: probabilities,
+            thresholds: thresholds,
+            aliases: aliases,
+        }
+    }
+
+    // Returns an index and the probability of picking it, or None if the table is empty.
+    fn sample(&self, rng: &mut dyn rand::RngCore) -> Option<(usize, f64)> {
+        if self.thresholds.is_empty() {
+            return None;
+        }
+        let n = self.thresholds.len();
+        let x = random(0.0, n as f64, rng);
+        let bucket = (x as usize).min(n - 1);
+        let index = if x - (bucket as f64) < self.thresholds[bucket] { bucket } else { self.aliases[bucket] };
+        Some((index, self.probabilities[index]))
+    }
+
+    fn probability(&self, index: usize) -> f64 {
+        self.probabilities[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand::rngs::SmallRng;
+
+    // The chance of each index, worked out from the buckets that keep it or alias to it.
+    fn implied_probabilities(table: &AliasTable) -> Vec<f64> {
+        let n = table.thresholds.len();
+        let mut probabilities = vec![0.0; n];
+        for bucket in 0..n {
+            probabilities[bucket] += table.thresholds[bucket] / n as f64;
+            probabilities[table.aliases[bucket]] += (1.0 - table.thresholds[bucket]) / n as f64;
+        }
+        probabilities
+    }
+
+    #[test]
+    fn alias_table_picks_in_proportion_to_weight() {
+        let weights = [1.0, 3.0, 0.0, 4.0, 2.0];
+        let table = AliasTable::new(&weights);
+        for (index, probability) in implied_probabilities(&table).into_iter().enumerate() {
+            assert!((probability - weights[index] / 10.0).abs() < 1e-12);
+            assert!((table.probability(index) - weights[index] / 10.0).abs() < 1e-12);
+        }
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut counts = [0; 5];
+        for _ in 0..100_000 {
+            let (index, probability) = table.sample(&mut rng).unwrap();
+            assert_eq!(probability, table.probability(index));
+            counts[index] += 1;
+        }
+        assert_eq!(counts[2], 0);
+        for (index, &count) in counts.iter().enumerate() {
+            assert!((count as f64 / 100_000.0 - weights[index] / 10.0).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn alias_table_falls_back_to_equal_weights() {
+        let table = AliasTable::new(&[0.0, 0.0, f64::NAN, -1.0]);
+        for probability in implied_probabilities(&table) {
+            assert!((probability - 0.25).abs() < 1e-12);
+        }
+        assert!(AliasTable::new(&[]).sample(&mut SmallRng::seed_from_u64(0)).is_none());
+    }
+
+    #[test]
+    fn light_list_chooses_by_power() {
+        let mut lights = LightList::new();
+        lights.add(Rc::new(PointLight::new(Vec3::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0))));
+        lights.add(Rc::new(DirectionalLight::new(Vec3::new(0.0, -1.0, 0.0), Color::new(1.0, 1.0, 1.0))));
+        lights.add(Rc::new(PointLight::new(Vec3::new(0.0, 0.0, 0.0), Color::new(3.0, 3.0, 3.0))));
+        let mut rng = SmallRng::seed_from_u64(0);
+        let (light, probability) = lights.choose(&mut rng).unwrap();
+        let expected = if light.power().unwrap() > 4.0 * std::f64::consts::PI { 0.75 } else { 0.25 };
+        assert!((probability - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn finds_sphere_lights_from_their_hits() {
+        let mut lights = LightList::new();
+        lights.add(Rc::new(DirectionalLight::new(Vec3::new(0.0, -1.0, 0.0), Color::new(1.0, 1.0, 1.0))));
+        let sphere = Rc::new(SphereLight::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Color::new(1.0, 1.0, 1.0)));
+        lights.add(sphere.clone());
+        let pi = std::f64::consts::PI;
+        lights.add(Rc::new(PointLight::new(Vec3::new(0.0, 0.0, 0.0), Color::new(pi, pi, pi))));
+        let unregistered = SphereLight::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Color::new(1.0, 1.0, 1.0));
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        assert_eq!(sphere.hit(&ray, &ray_t).unwrap().light(), Some(1));
+        assert_eq!(unregistered.hit(&ray, &ray_t).unwrap().light(), None);
+
+        // The sphere and the point light have the same power.
+        let (light, probability) = lights.find(1).unwrap();
+        assert!(light.normal(&Vec3::new(0.0, 0.0, 1.0)).is_some());
+        assert!((probability - 0.5).abs() < 1e-12);
+        assert!(lights.find(0).is_none());
+    }
+}
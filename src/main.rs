@@ -1,27 +1,59 @@
 // See the README for how to build and run
 
-use crate::camera::Camera;
+// Struct literals spell out `field: field`, and Camera::new takes the full set of view parameters.
+#![allow(clippy::redundant_field_names, clippy::too_many_arguments)]
+
+use crate::camera::{Camera, Integrator};
 use crate::color::Color;
-use crate::hittable_list::HittableList;
-use crate::material::{Dielectric, Lambertian, Metal};
-use crate::sphere::Sphere;
-use crate::util::{parse_aspect_ratio, random};
-use crate::vec3::Vec3;
+use crate::filter::{Filter, FilterKind};
+use crate::color_space::ColorSpace;
+use crate::dither::Dither;
+use crate::ies::IesProfile;
+use crate::output::{ImageFormat, ImageWriter};
+use crate::scene::{Scene, SceneName};
+use crate::tone_map::{ToneMapper, ToneMapping};
+use crate::util::parse_aspect_ratio;
 
 use clap::Parser;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
 
-use std::fs::File;
-use std::io::{BufWriter, Error, ErrorKind, Result};
+use std::fs::{self, File};
+use std::io::{Error, Result};
 use std::rc::Rc;
 
+mod alpha_mask;
+mod aov;
+mod bdpt;
 mod camera;
 mod color;
+mod color_space;
+mod debug;
+mod dither;
+mod denoise;
+mod film;
+mod filter;
 mod hit;
 mod hittable_list;
+mod ies;
 mod interval;
+mod light;
 mod material;
+mod medium;
+mod mlt;
+mod microfacet;
+mod normal_map;
+mod onb;
+mod output;
+mod png;
+mod principled;
 mod ray;
+mod scene;
+mod spectrum;
 mod sphere;
+mod sppm;
+mod texture;
+mod thin_film;
+mod tone_map;
 mod util;
 mod vec3;
 
@@ -74,77 +106,179 @@ struct Args {
     #[arg(long, default_value_t = 50)]
     max_depth: u32,
 
+    // Where to write the image, or - for stdout.
     #[arg(long, default_value_t = String::from("img\\a.ppm"))]
     out_file: String,
+
+    #[arg(long, value_enum, default_value_t = SceneName::Cover)]
+    scene: SceneName,
+
+    // Sample wavelengths per path instead of tracing RGB. Needed for dispersion.
+    #[arg(long)]
+    spectral: bool,
+
+    // IES photometric profile (.ies file) for the scene's point and spot lights.
+    #[arg(long)]
+    ies: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = Integrator::Path)]
+    integrator: Integrator,
+
+    // Photons shot from the lights and the sky in each iteration of the sppm integrator.
+    // Defaults to one per pixel.
+    #[arg(long)]
+    photons: Option<u32>,
+
+    // Initial radius around each visible point within which sppm gathers photons.
+    #[arg(long, default_value_t = 0.1)]
+    photon_radius: f64,
+
+    // Paths traced by the mlt integrator to estimate the image's brightness and pick where
+    // its Markov chains start.
+    #[arg(long, default_value_t = 100_000)]
+    mlt_bootstrap: u32,
+
+    // Markov chains the mlt integrator shares its samples out over.
+    #[arg(long, default_value_t = 1000)]
+    mlt_chains: u32,
+
+    // Distance within which objects occlude each other in the ao integrator.
+    #[arg(long, default_value_t = 1.0)]
+    ao_distance: f64,
+
+    // Also write the AOVs in aov.rs, each next to the image with its name before the
+    // extension. Only the path integrator renders them.
+    #[arg(long)]
+    aovs: bool,
+
+    // Smooth out the noise after rendering, guided by the albedo, normal and depth AOVs.
+    // Only works with the path integrator.
+    #[arg(long)]
+    denoise: bool,
+
+    // How far the denoiser spreads light between similar pixels, which must be positive.
+    #[arg(long, default_value_t = 1.0)]
+    denoise_strength: f64,
+
+    // How each camera sample is spread over the pixels around it.
+    #[arg(long, value_enum, default_value_t = FilterKind::Box)]
+    filter: FilterKind,
+
+    // Radius of the filter in pixels, which must be positive. Defaults to one that suits the
+    // filter.
+    #[arg(long)]
+    filter_radius: Option<f64>,
+
+    // Format of the output. Defaults to the one --out-file's extension names, or PPM.
+    #[arg(long, value_enum)]
+    format: Option<ImageFormat>,
+
+    // Write OpenEXR channels as 16-bit instead of 32-bit floats.
+    #[arg(long)]
+    half: bool,
+
+    // Exposure adjustment in stops, applied before tone mapping. Only affects PPM and PNG
+    // output.
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    exposure: f64,
+
+    // How radiance is brought into the range PPM and PNG can show.
+    #[arg(long, value_enum, default_value_t = ToneMapper::Clamp)]
+    tone_map: ToneMapper,
+
+    // Luminance that becomes white with extended Reinhard. Defaults to the brightest pixel's.
+    #[arg(long)]
+    white_point: Option<f64>,
+
+    // Color space that scene colors and the rendered radiance are in.
+    #[arg(long, value_enum, default_value_t = ColorSpace::Srgb)]
+    working_space: ColorSpace,
+
+    // Color space the image is written in.
+    #[arg(long, value_enum, default_value_t = ColorSpace::Srgb)]
+    output_space: ColorSpace,
+
+    // Bits per channel of PPM and PNG output, 8 or 16. PFM and OpenEXR ignore it.
+    #[arg(long, default_value_t = 8, value_parser = PossibleValuesParser::new(["8", "16"]).map(|bits| bits.parse::<u32>().unwrap()))]
+    bit_depth: u32,
+
+    // Noise added before quantizing PPM and PNG output, to break up banding.
+    #[arg(long, value_enum, default_value_t = Dither::None)]
+    dither: Dither,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
-    println!("{:?}", args);
+    eprintln!("{:?}", args);
+    if args.spectral && !matches!(args.integrator, Integrator::Path) {
+        return Err(Error::other("--spectral only works with the path integrator"));
+    }
+    if args.spectral && args.working_space != ColorSpace::Srgb {
+        return Err(Error::other("--spectral renders in sRGB, so it needs --working-space srgb"));
+    }
+    if args.aovs && !matches!(args.integrator, Integrator::Path) {
+        return Err(Error::other("--aovs only works with the path integrator"));
+    }
+    if args.denoise && !matches!(args.integrator, Integrator::Path) {
+        return Err(Error::other("--denoise only works with the path integrator"));
+    }
+    if !args.denoise_strength.is_finite() || args.denoise_strength <= 0.0 {
+        return Err(Error::other("--denoise-strength must be a positive number"));
+    }
+    // A filter that reaches no other sample gives every pixel a weight of zero.
+    if let Some(radius) = args.filter_radius && (!radius.is_finite() || radius <= 0.0) {
+        return Err(Error::other("--filter-radius must be a positive number of pixels"));
+    }
 
     let mut rng = rand::rng();
 
-    let file = File::create(args.out_file)?;
-    let mut file = BufWriter::new(file);
-
-    let mut world = HittableList::new();
-
-    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    world.add(Rc::new(Sphere::new(Vec3::new(0.0,-1000.0,0.0), 1000.0, ground_material)));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let center = Vec3::new((a as f64) + 0.9 * random(0.0, 1.0, &mut rng), 0.2, (b as f64) + 0.9 * random(0.0, 1.0, &mut rng));
-            if (center - Vec3::new(4.0, 0.2, 0.0)).len() > 0.9 {
-                let choose_material = random(0.0, 1.0, &mut rng);
-                match choose_material {
-                    0.0..0.8 => {
-                        let albedo = Color::random_vec(0.0, 1.0, &mut rng) * Color::random_vec(0.0, 1.0, &mut rng);
-                        let sphere_material = Rc::new(Lambertian::new(albedo));
-                        world.add(Rc::new(Sphere::new(center, 0.2, sphere_material)));
-                    },
-                    0.8..0.95 => {
-                        let albedo = Color::random_vec(0.5, 1.0, &mut rng);
-                        let fuzz = random(0.0, 0.5, &mut rng);
-                        let sphere_material = Rc::new(Metal::new(albedo, fuzz));
-                        world.add(Rc::new(Sphere::new(center, 0.2, sphere_material)));
-                    },
-                    _ => {
-                        let sphere_material = Rc::new(Dielectric::new(1.5));
-                        world.add(Rc::new(Sphere::new(center, 0.2, sphere_material)));
-                    }
-                }
-            }
-        }
+    let format = args.format.or(ImageFormat::from_path(&args.out_file)).unwrap_or(ImageFormat::Ppm);
+    let to_stdout = args.out_file == "-";
+    if to_stdout && args.aovs && !matches!(format, ImageFormat::Exr) {
+        return Err(Error::other("--aovs can only go to stdout as layers of an OpenEXR image"));
+    }
+    // Fail before rendering if the image can't be written.
+    if !to_stdout {
+        File::create(&args.out_file)?;
     }
 
-    let material1 = Rc::new(Dielectric::new(1.5));
-    world.add(Rc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, material1)));
+    let ies_profile = match &args.ies {
+        Some(path) => Some(Rc::new(IesProfile::parse(&fs::read_to_string(path)?).map_err(Error::other)?)),
+        None => None,
+    };
+    let scene = Scene::new(args.scene, ies_profile, &mut rng);
+    // Photons only come from the registered lights and the background.
+    if matches!(args.integrator, Integrator::Sppm) && scene.lights().power() <= 0.0 && scene.average_background().is_near_zero() {
+        return Err(Error::other("--integrator sppm needs a scene with lights or a lit background"));
+    }
 
-    let material2 = Rc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Rc::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, material2)));
-
-    let material3 = Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Rc::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, material3)));
-    
-    let (requested_width, requested_height) = parse_aspect_ratio(&args.aspect_ratio).map_err(|s: &str| Error::new(ErrorKind::Other, s))?;
+    let (requested_width, requested_height) = parse_aspect_ratio(&args.aspect_ratio).map_err(|s: &str| Error::other(s))?;
 
     let aspect_ratio      = requested_width / requested_height;
     let image_width       = args.image_width;
     let samples_per_pixel = args.samples_per_pixel;
     let max_depth         = args.max_depth;
 
-    let vertical_fov_degrees = 20.0;
-    let look_from = Vec3::new(13.0,2.0,3.0);
-    let look_at  = Vec3::new(0.0,0.0,0.0);
-    let view_up  = Vec3::new(0.0,1.0,0.0);
-
-    let defocus_angle_degrees = 0.6;
-    let focus_distance    = 10.0;
+    let camera = Camera::new(aspect_ratio, image_width, samples_per_pixel, max_depth, scene.vertical_fov_degrees(), scene.look_from(), scene.look_at(), scene.view_up(), scene.defocus_angle_degrees(), scene.focus_distance())
+        .with_spectral(args.spectral)
+        .with_integrator(args.integrator)
+        .with_photons(args.photons, args.photon_radius)
+        .with_mlt(args.mlt_bootstrap, args.mlt_chains)
+        .with_ao_distance(args.ao_distance)
+        .with_aovs(args.aovs || args.denoise)
+        .with_filter(Filter::new(args.filter, args.filter_radius));
 
-    let camera = Camera::new(aspect_ratio, image_width, samples_per_pixel, max_depth, vertical_fov_degrees, &look_from, &look_at, &view_up, defocus_angle_degrees, focus_distance);
-
-    camera.render(&world, &mut file, &mut rng)?;
+    let mut film = camera.render(&scene, &mut rng);
+    if args.denoise {
+        denoise::denoise(&mut film, args.denoise_strength);
+    }
+    let aovs: Vec<(&str, Vec<Color>)> = if args.aovs { film.aovs().collect() } else { vec![] };
+    ImageWriter::new(format)
+        .with_half(args.half)
+        .with_tone_mapping(ToneMapping::new(args.tone_map, args.exposure, args.white_point))
+        .with_color_spaces(args.working_space, args.output_space)
+        .with_quantization(args.bit_depth == 16, args.dither)
+        .write(&args.out_file, film.width(), film.height(), &film.pixels(), &aovs)?;
 
     Ok(())
 }
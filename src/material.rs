@@ -1,22 +1,102 @@
 use crate::color::Color;
 use crate::hit::HitRecord;
+use crate::medium::Medium;
+use crate::microfacet::{Ggx, schlick};
 use crate::ray::Ray;
-use crate::util::random;
+use crate::texture::{SolidColor, Texture};
+use crate::thin_film::{ThinFilm, metal_index};
+use crate::util::{degrees_to_radians, random};
 use crate::vec3::Vec3;
 
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 pub struct ScatterResult {
     scattered: Ray,  // The scattered ray
     attenuation: Color,  // Brightness of the scattered ray relative to the incoming ray
+    dispersive: bool,  // The scattered direction depends on the wavelength of the incoming ray
+
+    // The attenuation holds values at the incoming ray's sampled wavelengths instead of
+    // RGB. Only ever set for rays that carry wavelengths.
+    spectral: bool,
+
+    // The direction was sampled from the part of the material that `Material::evaluate`
+    // covers, so light sources it reaches were already counted by direct lighting.
+    evaluated: bool,
 }
 
 impl ScatterResult {
+    // For materials outside this module. The result is treated as not dispersive.
+    pub fn new(scattered: Ray, attenuation: Color) -> Self {
+        Self {
+            scattered: scattered,
+            attenuation: attenuation,
+            dispersive: false,
+            spectral: false,
+            evaluated: false,
+        }
+    }
+
+    // Scales the attenuation, e.g. for a layer the light has to pass through first.
+    pub fn attenuated(mut self, by: Color) -> Self {
+        self.attenuation = self.attenuation * by;
+        self
+    }
+
+    pub fn with_evaluated(mut self, evaluated: bool) -> Self {
+        self.evaluated = evaluated;
+        self
+    }
+
+    // Sends the scattered ray in a different direction from the same origin.
+    pub fn with_direction(mut self, direction: Vec3) -> Self {
+        self.scattered = Ray::new(*self.scattered.orig(), direction);
+        self
+    }
+
     pub fn scattered(&self) -> &Ray { &self.scattered }
     pub fn attenuation(&self) -> &Color { &self.attenuation }
+    pub fn dispersive(&self) -> bool { self.dispersive }
+    pub fn spectral(&self) -> bool { self.spectral }
+    pub fn evaluated(&self) -> bool { self.evaluated }
+}
+
+// Numbers materials in the order they are made, which stays the same from run to run
+// for a scene built the same way, unlike their addresses.
+static MATERIAL_COUNT: AtomicU64 = AtomicU64::new(0);
+
+pub fn next_id() -> u64 {
+    MATERIAL_COUNT.fetch_add(1, Ordering::Relaxed)
 }
 
 // A trait for material types to implement.
 pub trait Material {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut rand::rngs::ThreadRng) -> Option<ScatterResult>;
+    // The number `next_id` gave the material when it was made.
+    fn id(&self) -> u64;
+
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult>;
+
+    // Light given off by the surface itself towards the start of `ray`.
+    fn emitted(&self, _ray: &Ray, _hit_record: &HitRecord) -> Color { Color::new(0.0, 0.0, 0.0) }
+
+    // The fraction of light arriving from `direction` that leaves back along `ray`, times
+    // the cosine between `direction` and the normal. Materials that can only be sampled
+    // return None and are lit by the scene's lights only through their scattered rays.
+    fn evaluate(&self, _ray: &Ray, _hit_record: &HitRecord, _direction: &Vec3) -> Option<Color> { None }
+
+    // Probability density per unit solid angle of `scatter` sending `ray` off in `direction`
+    // from the part of the material that `evaluate` covers.
+    fn pdf(&self, _ray: &Ray, _hit_record: &HitRecord, _direction: &Vec3) -> f64 { 0.0 }
+
+    // The normal the material shades the hit with, pointing out of the object. Materials
+    // that bend the normal (see `normal_map`) override this; the rest use the surface's.
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        if hit_record.front_face() { *hit_record.normal() } else { -*hit_record.normal() }
+    }
+
+    // The medium filling the inside of objects with this material, if any. The camera
+    // samples it for rays that reach the material's surface from the inside.
+    fn interior(&self) -> Option<&Medium> { None }
 }
 
 pub struct Lambertian {
@@ -24,18 +104,22 @@ pub struct Lambertian {
     // `albedo` denotes the attenuation experienced by a scattered ray. When
     // it is 1.0, the scattered ray has the same brightness as the incoming ray
     albedo: Color,
+    id: u64,  // See `next_id`
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
         Self {
             albedo: albedo,
+            id: next_id(),
         }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _: &Ray, hit_record: &HitRecord, rng: &mut rand::rngs::ThreadRng) -> Option<ScatterResult> {
+    fn id(&self) -> u64 { self.id }
+
+    fn scatter(&self, _: &Ray, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
         let mut scatter_direction = *hit_record.normal() + Vec3::uniform_random_unit_vec(rng);
 
         // Catch degenerate scatter directions. These result from uniformly sampled random unit vectors
@@ -46,14 +130,119 @@ impl Material for Lambertian {
 
         Some(ScatterResult {
             scattered: Ray::new(*hit_record.point(), scatter_direction),
-            attenuation: self.albedo
+            attenuation: self.albedo,
+            dispersive: false,
+            spectral: false,
+            evaluated: true,
         })
     }
+
+    fn evaluate(&self, _: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Option<Color> {
+        let cos = direction.unit_vec().dot(hit_record.normal()).max(0.0);
+        Some(self.albedo * (cos / std::f64::consts::PI))
+    }
+
+    fn pdf(&self, _: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        cosine_pdf(hit_record.normal(), direction)
+    }
+}
+
+// Density of the cosine-weighted hemisphere around `normal` that Lambertian samples.
+fn cosine_pdf(normal: &Vec3, direction: &Vec3) -> f64 {
+    direction.unit_vec().dot(normal).max(0.0) / std::f64::consts::PI
 }
 
+// Rough diffuse reflection from Oren and Nayar (1994), using their qualitative model.
+// Treats the surface as V-shaped grooves with a Gaussian slope distribution of standard
+// deviation `sigma`, which brightens it towards the light and flattens it, as with clay,
+// concrete or the full moon. A sigma of 0 is Lambertian.
+pub struct OrenNayar {
+    albedo: Color,
+    a: f64,
+    b: f64,
+    id: u64,  // See `next_id`
+}
+
+impl OrenNayar {
+    // `sigma_degrees` is the standard deviation of the groove slopes, usually 0 to 40 or so.
+    pub fn new(albedo: Color, sigma_degrees: f64) -> Self {
+        let sigma = degrees_to_radians(sigma_degrees.clamp(0.0, 90.0));
+        let sigma_sq = sigma * sigma;
+        Self {
+            albedo: albedo,
+            a: 1.0 - 0.5 * sigma_sq / (sigma_sq + 0.33),
+            b: 0.45 * sigma_sq / (sigma_sq + 0.09),
+            id: next_id(),
+        }
+    }
+
+    // Ratio of the BRDF to the Lambertian one with the same albedo. `wo` and `wi` point away
+    // from the surface and all vectors are normalized.
+    fn factor(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> f64 {
+        let cos_o = wo.dot(normal).clamp(0.0, 1.0);
+        let cos_i = wi.dot(normal).clamp(0.0, 1.0);
+        let sin_o = (1.0 - cos_o * cos_o).sqrt();
+        let sin_i = (1.0 - cos_i * cos_i).sqrt();
+
+        // cos(phi_i - phi_o) from the projections onto the tangent plane.
+        let cos_phi_diff = if sin_o > 1e-4 && sin_i > 1e-4 {
+            let projected_o = (*wo - cos_o * *normal) / sin_o;
+            let projected_i = (*wi - cos_i * *normal) / sin_i;
+            projected_o.dot(&projected_i).max(0.0)
+        } else {
+            0.0
+        };
+
+        // sin(alpha) * tan(beta), where alpha is the larger of the two angles and beta the smaller.
+        let (sin_alpha, tan_beta) = if cos_i > cos_o {
+            (sin_o, sin_i / cos_i.max(1e-4))
+        } else {
+            (sin_i, sin_o / cos_o.max(1e-4))
+        };
+        self.a + self.b * cos_phi_diff * sin_alpha * tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    fn id(&self) -> u64 { self.id }
+
+    // Samples the same cosine-weighted hemisphere as Lambertian, so the weight is the albedo
+    // scaled by the ratio to Lambertian.
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
+        let mut scatter_direction = *hit_record.normal() + Vec3::uniform_random_unit_vec(rng);
+        if scatter_direction.is_near_zero() {
+            scatter_direction = *hit_record.normal();
+        }
+
+        let wo = -ray.dir().unit_vec();
+        let factor = self.factor(&wo, &scatter_direction.unit_vec(), hit_record.normal());
+        Some(ScatterResult::new(Ray::new(*hit_record.point(), scatter_direction), factor * self.albedo).with_evaluated(true))
+    }
+
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Option<Color> {
+        let wi = direction.unit_vec();
+        let cos = wi.dot(hit_record.normal());
+        if cos <= 0.0 {
+            return Some(Color::new(0.0, 0.0, 0.0));
+        }
+        let factor = self.factor(&-ray.dir().unit_vec(), &wi, hit_record.normal());
+        Some(self.albedo * (factor * cos / std::f64::consts::PI))
+    }
+
+    fn pdf(&self, _: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        cosine_pdf(hit_record.normal(), direction)
+    }
+}
+
+// Fuzz below this makes the reflection too narrow for light sampling to find, so such metals
+// are only lit through their scattered rays, like mirrors.
+const MIN_EVALUATED_FUZZ: f64 = 0.1;
+
 pub struct Metal {
     albedo: Color,
     fuzz: f64,  // Used the randomize the direction of the reflected ray.
+    thin_film: Option<ThinFilm>,  // E.g. an oxide layer
+    id: u64,  // See `next_id`
 }
 
 impl Metal {
@@ -61,40 +250,212 @@ impl Metal {
         Self {
             albedo: albedo,
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
+            thin_film: None,
+            id: next_id(),
         }
     }
+
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    // Only plain metals that are rough enough can be evaluated. A thin film's reflectance
+    // varies with wavelength, which evaluating in RGB can't express.
+    fn can_evaluate(&self) -> bool {
+        self.thin_film.is_none() && self.fuzz >= MIN_EVALUATED_FUZZ
+    }
+
+    // Probability density per unit solid angle of `scatter` picking `direction`, including
+    // directions below the surface that it absorbs. The fuzzed direction points at a uniformly
+    // chosen point on a sphere of radius `fuzz` around the tip of the mirror direction, so
+    // the density sums, over the points where `direction` crosses that sphere, the area to
+    // solid angle factor distance^2 / cos over the sphere's area.
+    fn fuzz_pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        let reflected = ray.dir().unit_vec().reflect(hit_record.normal());
+        let cos_to_reflected = direction.unit_vec().dot(&reflected);
+        let discriminant = cos_to_reflected * cos_to_reflected - 1.0 + self.fuzz * self.fuzz;
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        // The cosine at both crossings is root / fuzz.
+        let root = discriminant.sqrt();
+        [cos_to_reflected - root, cos_to_reflected + root].iter()
+            .filter(|distance| **distance > 0.0)
+            .map(|distance| distance * distance / (4.0 * std::f64::consts::PI * self.fuzz * root))
+            .sum()
+    }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut rand::rngs::ThreadRng) -> Option<ScatterResult> {
+    fn id(&self) -> u64 { self.id }
+
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
         let reflected = ray.dir().reflect(hit_record.normal());
         let fuzzed = reflected.unit_vec() + (self.fuzz * Vec3::uniform_random_unit_vec(rng));
         let scattered = Ray::new(*hit_record.point(), fuzzed);
-        if scattered.dir().dot(hit_record.normal()) > 0.0 {
-            Some(ScatterResult {
+        if scattered.dir().dot(hit_record.normal()) <= 0.0 {
+            return None;
+        }
+
+        match self.thin_film {
+            None => Some(ScatterResult {
                 attenuation: self.albedo,
                 scattered: scattered,
-            })
-        } else {
-            None
+                dispersive: false,
+                spectral: false,
+                evaluated: self.can_evaluate(),
+            }),
+            Some(thin_film) => {
+                // The metal under the film is modelled by the (n, k) that reproduce its albedo.
+                let albedo = match ray.wavelengths() {
+                    Some(wavelengths) => wavelengths.lift_rgb(&self.albedo),
+                    None => self.albedo,
+                };
+                let substrate = [metal_index(albedo.x()), metal_index(albedo.y()), metal_index(albedo.z())];
+                let cos_theta = -ray.dir().unit_vec().dot(hit_record.normal());
+                Some(ScatterResult {
+                    attenuation: thin_film.reflectance(ray, cos_theta, 1.0, &substrate),
+                    scattered: scattered,
+                    dispersive: false,
+                    spectral: ray.wavelengths().is_some(),
+                    evaluated: false,
+                })
+            },
         }
     }
+
+    // Every scattered direction above the surface carries the albedo, so the BRDF times the
+    // cosine is the albedo times the density of picking the direction.
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Option<Color> {
+        if !self.can_evaluate() {
+            return None;
+        }
+        if direction.dot(hit_record.normal()) <= 0.0 {
+            return Some(Color::new(0.0, 0.0, 0.0));
+        }
+        Some(self.albedo * self.fuzz_pdf(ray, hit_record, direction))
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        if !self.can_evaluate() || direction.dot(hit_record.normal()) <= 0.0 {
+            return 0.0;
+        }
+        self.fuzz_pdf(ray, hit_record, direction)
+    }
+}
+
+// Wavelength at which curves are evaluated when rendering in RGB: the Fraunhofer d line,
+// which is where catalog values like "n = 1.5168" are quoted.
+const RGB_WAVELENGTH_NM: f64 = 587.56;
+
+// Refractive index as a function of wavelength. Curve coefficients use wavelengths in
+// micrometers, as glass catalogs do.
+#[derive(Clone, Copy, Debug)]
+pub enum RefractiveIndex {
+    Constant(f64),
+    // n = a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum of b[i] * lambda^2 / (lambda^2 - c[i])
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl RefractiveIndex {
+    // Schott N-BK7, the common crown glass.
+    pub fn bk7() -> Self {
+        RefractiveIndex::Sellmeier { b: [1.03961212, 0.231792344, 1.01046945], c: [0.00600069867, 0.0200179144, 103.560653] }
+    }
+
+    // Schott N-SF11, a dense flint glass with strong dispersion.
+    pub fn dense_flint() -> Self {
+        RefractiveIndex::Sellmeier { b: [1.73759695, 0.313747346, 1.89878101], c: [0.013188707, 0.0623068142, 155.23629] }
+    }
+
+    pub fn diamond() -> Self {
+        RefractiveIndex::Sellmeier { b: [4.3356, 0.3306, 0.0], c: [0.0112360, 0.030625, 0.0] }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, RefractiveIndex::Constant(_))
+    }
+
+    // `wavelength_nm` is the ray's wavelength, or None when rendering in RGB.
+    pub fn at(&self, wavelength_nm: Option<f64>) -> f64 {
+        let lambda = wavelength_nm.unwrap_or(RGB_WAVELENGTH_NM) / 1000.0;
+        let lambda_sq = lambda * lambda;
+        let n = match self {
+            RefractiveIndex::Constant(n) => *n,
+            RefractiveIndex::Cauchy { a, b } => a + b / lambda_sq,
+            RefractiveIndex::Sellmeier { b, c } => {
+                let mut n_sq = 1.0;
+                for i in 0..3 {
+                    n_sq += b[i] * lambda_sq / (lambda_sq - c[i]);
+                }
+                n_sq.max(0.0).sqrt()
+            },
+        };
+        // Disallow negative values.
+        if n > 0.0 { n } else { 1.0 }
+    }
 }
 
 pub struct Dielectric {
     // Refractive index in vacuum or air, or the ratio of the material's refractive index over
     // the refractive index of the enclosing media
-    refractive_index: f64,
+    refractive_index: RefractiveIndex,
+
+    // Beer-Lambert absorption coefficient of the interior, per color channel and per unit
+    // of distance. Zero means perfectly clear glass.
+    absorption: Color,
+
+    // Coating on the outside of the surface, e.g. the soap film of a bubble.
+    thin_film: Option<ThinFilm>,
+    id: u64,  // See `next_id`
 }
 
 impl Dielectric {
     pub fn new(refractive_index: f64) -> Self {
+        Self::dispersive(RefractiveIndex::Constant(refractive_index))
+    }
+
+    // Dispersion only shows up in spectral mode. In RGB mode the curve is evaluated at a
+    // single wavelength.
+    pub fn dispersive(refractive_index: RefractiveIndex) -> Self {
         Self {
-            // Disallow negative values.
-            refractive_index: if refractive_index > 0.0 { refractive_index } else { 1.0 },
+            refractive_index: refractive_index,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            thin_film: None,
+            id: next_id(),
         }
     }
 
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    // Negative coefficients would amplify light, so they're clamped to zero.
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = Color::new(absorption.x().max(0.0), absorption.y().max(0.0), absorption.z().max(0.0));
+        self
+    }
+
+    // Artist-friendly form of `with_absorption`: white light that travels `distance`
+    // through the interior comes out as `color`.
+    pub fn with_transmission_color(self, color: Color, distance: f64) -> Self {
+        let coefficient = |c: f64| if c > 0.0 && distance > 0.0 { -c.min(1.0).ln() / distance } else { 0.0 };
+        self.with_absorption(Color::new(coefficient(color.x()), coefficient(color.y()), coefficient(color.z())))
+    }
+
+    // Attenuation of a ray that travelled `distance` through the interior.
+    fn transmittance(&self, distance: f64) -> Color {
+        Color::new(
+            (-self.absorption.x() * distance).exp(),
+            (-self.absorption.y() * distance).exp(),
+            (-self.absorption.z() * distance).exp(),
+        )
+    }
+
     fn reflectance(cos: f64, refractive_index: f64) -> f64 {
         // Use Schlick's approximation for reflectance.
         let r0 = (1.0 - refractive_index) / (1.0 + refractive_index);
@@ -104,22 +465,388 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut rand::rngs::ThreadRng) -> Option<ScatterResult> {
-        let relative_refractive_index = if hit_record.front_face() { 1.0 / self.refractive_index } else { self.refractive_index };
+    fn id(&self) -> u64 { self.id }
+
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
+        let refractive_index = self.refractive_index.at(ray.wavelength());
+        let relative_refractive_index = if hit_record.front_face() { 1.0 / refractive_index } else { refractive_index };
         let unit_direction = ray.dir().unit_vec();
         let cos_theta = -unit_direction.dot(hit_record.normal());
         let sin_theta = (1.0 - cos_theta*cos_theta).sqrt();
 
         let cannot_refract = relative_refractive_index * sin_theta > 1.0;
 
-        let direction = if cannot_refract || Dielectric::reflectance(cos_theta, relative_refractive_index) > random(0.0, 1.0, rng) {
+        // A ray hitting the back face has been travelling through the interior since it
+        // entered at the previous hit, so the segment length is the distance between the
+        // entry and exit hits. This assumes dielectrics aren't nested inside each other.
+        let absorption = if hit_record.front_face() {
+            Color::new(1.0, 1.0, 1.0)
+        } else {
+            self.transmittance(hit_record.t() * ray.dir().len())
+        };
+
+        let (reflect, attenuation, spectral) = match self.thin_film {
+            None => (cannot_refract || Dielectric::reflectance(cos_theta, relative_refractive_index) > random(0.0, 1.0, rng), absorption, false),
+            Some(thin_film) => {
+                // The film's reflectance varies with wavelength, so reflect with its average
+                // and reweight each channel or wavelength by its own value.
+                let (incident_index, substrate_index) = if hit_record.front_face() { (1.0, refractive_index) } else { (refractive_index, 1.0) };
+                let reflectance = if cannot_refract {
+                    Vec3::new(1.0, 1.0, 1.0)
+                } else {
+                    thin_film.reflectance(ray, cos_theta, incident_index, &[(substrate_index, 0.0); 3])
+                };
+                let probability = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0;
+                let absorption = match ray.wavelengths() {
+                    Some(wavelengths) => wavelengths.lift_rgb(&absorption),
+                    None => absorption,
+                };
+                if probability > random(0.0, 1.0, rng) {
+                    (true, absorption * reflectance / probability, ray.wavelengths().is_some())
+                } else {
+                    (false, absorption * (Vec3::new(1.0, 1.0, 1.0) - reflectance) / (1.0 - probability), ray.wavelengths().is_some())
+                }
+            },
+        };
+
+        let direction = if reflect {
             unit_direction.reflect(hit_record.normal())
         } else {
             Vec3::refract(&unit_direction, hit_record.normal(), relative_refractive_index)
         };
+
         Some(ScatterResult {
             scattered: Ray::new(*hit_record.point(), direction),
-            attenuation: Color::new(1.0, 1.0, 1.0),
+            attenuation: attenuation,
+            spectral: spectral,
+            evaluated: false,
+            dispersive: self.refractive_index.is_dispersive(),
         })
     }
 }
+
+// Picks one of two materials at random for each scatter. The weight is the probability of
+// picking `second`, and is the average of the texture's channels at the hit point.
+pub struct Mix {
+    first: Rc<dyn Material>,
+    second: Rc<dyn Material>,
+    weight: Rc<dyn Texture>,
+    id: u64,  // See `next_id`
+}
+
+impl Mix {
+    pub fn new(first: Rc<dyn Material>, second: Rc<dyn Material>, weight: f64) -> Self {
+        Self::textured(first, second, Rc::new(SolidColor::new(Color::new(weight, weight, weight))))
+    }
+
+    pub fn textured(first: Rc<dyn Material>, second: Rc<dyn Material>, weight: Rc<dyn Texture>) -> Self {
+        Self {
+            first: first,
+            second: second,
+            weight: weight,
+            id: next_id(),
+        }
+    }
+}
+
+impl Material for Mix {
+    fn id(&self) -> u64 { self.id }
+
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
+        let weight = self.weight.value(hit_record.u(), hit_record.v(), hit_record.point());
+        let weight = (weight.x() + weight.y() + weight.z()) / 3.0;
+        if weight > random(0.0, 1.0, rng) {
+            self.second.scatter(ray, hit_record, rng)
+        } else {
+            self.first.scatter(ray, hit_record, rng)
+        }
+    }
+
+    // Materials that can't be evaluated contribute nothing, as if they were black.
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Option<Color> {
+        let weight = self.weight.value(hit_record.u(), hit_record.v(), hit_record.point());
+        let weight = (weight.x() + weight.y() + weight.z()) / 3.0;
+        match (self.first.evaluate(ray, hit_record, direction), self.second.evaluate(ray, hit_record, direction)) {
+            (None, None) => None,
+            (first, second) => {
+                let black = Color::new(0.0, 0.0, 0.0);
+                Some((1.0 - weight) * first.unwrap_or(black) + weight * second.unwrap_or(black))
+            },
+        }
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        let weight = self.weight.value(hit_record.u(), hit_record.v(), hit_record.point());
+        let weight = (weight.x() + weight.y() + weight.z()) / 3.0;
+        (1.0 - weight) * self.first.pdf(ray, hit_record, direction) + weight * self.second.pdf(ray, hit_record, direction)
+    }
+}
+
+// A thin dielectric layer over any other material, like varnish or a car's clearcoat.
+// Light either reflects off the coat, in proportion to its Fresnel reflectance, or passes
+// through it to the base, picking up the coat's tint on the way in and out. Refraction of
+// directions inside the coat and reflections between the coat and the base are ignored.
+// The coat can be evaluated when it is rough, and the base through the coat when the base
+// can be.
+pub struct Coated {
+    base: Rc<dyn Material>,
+    refractive_index: f64,
+    roughness: f64,
+
+    // Color of light after one pass through the coat at normal incidence. Rays at grazing
+    // angles travel further through the coat and are tinted more strongly.
+    tint: Color,
+    id: u64,  // See `next_id`
+}
+
+impl Coated {
+    pub fn new(base: Rc<dyn Material>, refractive_index: f64) -> Self {
+        Self {
+            base: base,
+            refractive_index: if refractive_index > 0.0 { refractive_index } else { 1.0 },
+            roughness: 0.0,
+            tint: Color::new(1.0, 1.0, 1.0),
+            id: next_id(),
+        }
+    }
+
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.roughness = roughness.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = Color::new(tint.x().clamp(0.0, 1.0), tint.y().clamp(0.0, 1.0), tint.z().clamp(0.0, 1.0));
+        self
+    }
+
+    // Tint for a path through the coat whose cosines with the normal on the way in and out
+    // are `cos_in` and `cos_out`.
+    fn transmittance(&self, cos_in: f64, cos_out: f64) -> Color {
+        let path_length = 1.0 / cos_in.max(1e-3) + 1.0 / cos_out.max(1e-3);
+        Color::new(self.tint.x().powf(path_length), self.tint.y().powf(path_length), self.tint.z().powf(path_length))
+    }
+
+    // Fresnel reflectance of the coat for light leaving at `cos` to the (micro)normal.
+    fn reflectance(&self, cos: f64) -> f64 {
+        let r0 = (1.0 - self.refractive_index) / (1.0 + self.refractive_index);
+        schlick(r0 * r0, cos)
+    }
+}
+
+impl Material for Coated {
+    fn id(&self) -> u64 { self.id }
+
+    // Reflects off the coat with probability equal to its reflectance towards the viewer, and
+    // otherwise scatters off the base, so neither needs reweighting by its probability.
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
+        let wo = -ray.dir().unit_vec();
+        let normal = hit_record.normal();
+        let reflectance = self.reflectance(wo.dot(normal));
+        if reflectance > random(0.0, 1.0, rng) {
+            let ggx = Ggx::new(self.roughness);
+            let h = ggx.sample_half_vector(normal, rng);
+            let wi = ray.dir().unit_vec().reflect(&h);
+            if wi.dot(normal) <= 0.0 {
+                return None;
+            }
+            let weight = self.reflectance(wo.dot(&h)) / reflectance * ggx.sample_weight(&wo, &wi, &h, normal);
+            return Some(ScatterResult::new(Ray::new(*hit_record.point(), wi), Color::new(weight, weight, weight)).with_evaluated(!ggx.is_smooth()));
+        }
+
+        let result = self.base.scatter(ray, hit_record, rng)?;
+        let cos_out = result.scattered().dir().unit_vec().dot(normal).abs();
+        let tint = self.transmittance(wo.dot(normal).abs(), cos_out);
+        // A spectral attenuation holds values at the ray's wavelengths, so the tint must too.
+        let tint = match ray.wavelengths() {
+            Some(wavelengths) if result.spectral() => wavelengths.lift_rgb(&tint),
+            _ => tint,
+        };
+        Some(result.attenuated(tint))
+    }
+
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Option<Color> {
+        let wo = -ray.dir().unit_vec();
+        let wi = direction.unit_vec();
+        let normal = hit_record.normal();
+        let ggx = Ggx::new(self.roughness);
+        let coat = (!ggx.is_smooth()).then(|| {
+            let h = wo + wi;
+            let reflection = if h.is_near_zero() { 0.0 } else { self.reflectance(wo.dot(&h.unit_vec())) * ggx.reflection(&wo, &wi, normal) };
+            Color::new(reflection, reflection, reflection)
+        });
+        let base = self.base.evaluate(ray, hit_record, direction).map(|base| {
+            let cos_o = wo.dot(normal);
+            (1.0 - self.reflectance(cos_o)) * self.transmittance(cos_o.abs(), wi.dot(normal).abs()) * base
+        });
+        match (coat, base) {
+            (None, None) => None,
+            (coat, base) => {
+                let black = Color::new(0.0, 0.0, 0.0);
+                Some(coat.unwrap_or(black) + base.unwrap_or(black))
+            },
+        }
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        let wo = -ray.dir().unit_vec();
+        let normal = hit_record.normal();
+        let reflectance = self.reflectance(wo.dot(normal));
+        let ggx = Ggx::new(self.roughness);
+        let coat = if ggx.is_smooth() { 0.0 } else { ggx.reflection_pdf(&wo, &direction.unit_vec(), normal) };
+        reflectance * coat + (1.0 - reflectance) * self.base.pdf(ray, hit_record, direction)
+    }
+}
+
+// Random-walk subsurface scattering for skin, wax, marble, milk and the like. Light refracts
+// into the object through a smooth dielectric boundary, then scatters around inside the
+// medium until it's absorbed or finds its way out. The object must be closed, and the walk
+// counts against the camera's max depth, so dense media may need a higher --max-depth.
+pub struct Subsurface {
+    boundary: Dielectric,
+    medium: Medium,
+    id: u64,  // See `next_id`
+}
+
+impl Subsurface {
+    pub fn new(refractive_index: f64, medium: Medium) -> Self {
+        Self {
+            boundary: Dielectric::new(refractive_index),
+            medium: medium,
+            id: next_id(),
+        }
+    }
+}
+
+impl Material for Subsurface {
+    fn id(&self) -> u64 { self.id }
+
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
+        self.boundary.scatter(ray, hit_record, rng)
+    }
+
+    fn interior(&self) -> Option<&Medium> { Some(&self.medium) }
+}
+
+// A surface that glows with the same radiance in every direction on its front side, and
+// reflects nothing. Build emissive objects with `light::SphereLight`, which also registers
+// them for direct sampling. Emission from objects that aren't registered is only found by
+// paths that happen to hit them.
+pub struct DiffuseLight {
+    emission: Color,
+    id: u64,  // See `next_id`
+}
+
+impl DiffuseLight {
+    pub fn new(emission: Color) -> Self {
+        Self {
+            emission: emission,
+            id: next_id(),
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn id(&self) -> u64 { self.id }
+
+    fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut dyn rand::RngCore) -> Option<ScatterResult> {
+        None
+    }
+
+    fn emitted(&self, _: &Ray, hit_record: &HitRecord) -> Color {
+        if hit_record.front_face() { self.emission } else { Color::new(0.0, 0.0, 0.0) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::principled::Principled;
+    use crate::spectrum::SampledWavelengths;
+
+    use rand::SeedableRng;
+    use rand::rngs::SmallRng;
+
+    const SAMPLES: usize = 400_000;
+
+    // Checks the part of `material` that `evaluate` covers against what `scatter` does. Over
+    // a test function of direction, the scattered rays marked as evaluated must average to
+    // the integral of `evaluate`, and `pdf` must integrate to the share of them.
+    fn check_evaluate(material: Rc<dyn Material>) {
+        let wo = Vec3::new(0.5, 1.0, 0.2).unit_vec();
+        let ray = Ray::new(wo, -wo);
+        let hit_record = HitRecord::new(&Vec3::new(0.0, 0.0, 0.0), &ray, 1.0, &Vec3::new(0.0, 1.0, 0.0), material.clone());
+        let test_function = |direction: &Vec3| 1.0 + direction.x() + 0.5 * direction.y() * direction.y();
+        let average = |color: &Color| (color.x() + color.y() + color.z()) / 3.0;
+        let mut rng = SmallRng::seed_from_u64(1);
+
+        let (mut scattered, mut share) = (0.0, 0.0);
+        for _ in 0..SAMPLES {
+            let Some(result) = material.scatter(&ray, &hit_record, &mut rng) else { continue };
+            if result.evaluated() {
+                scattered += average(result.attenuation()) * test_function(&result.scattered().dir().unit_vec());
+                share += 1.0;
+            }
+        }
+
+        // Uniform directions over the sphere, each standing for 4 pi steradians.
+        let (mut evaluated, mut integrated_pdf) = (0.0, 0.0);
+        for _ in 0..SAMPLES {
+            let direction = Vec3::uniform_random_unit_vec(&mut rng);
+            let value = material.evaluate(&ray, &hit_record, &direction).unwrap();
+            evaluated += 4.0 * std::f64::consts::PI * average(&value) * test_function(&direction);
+            integrated_pdf += 4.0 * std::f64::consts::PI * material.pdf(&ray, &hit_record, &direction);
+        }
+
+        let (scattered, share) = (scattered / SAMPLES as f64, share / SAMPLES as f64);
+        let (evaluated, integrated_pdf) = (evaluated / SAMPLES as f64, integrated_pdf / SAMPLES as f64);
+        assert!((scattered - evaluated).abs() < 0.02 * scattered.max(0.1), "scattered {scattered}, evaluated {evaluated}");
+        assert!((share - integrated_pdf).abs() < 0.02, "share {share}, integrated pdf {integrated_pdf}");
+    }
+
+    #[test]
+    fn evaluates_rough_metal() {
+        check_evaluate(Rc::new(Metal::new(Color::new(0.9, 0.6, 0.3), 0.6)));
+    }
+
+    #[test]
+    fn evaluates_principled() {
+        let plastic = Principled::new(Color::new(0.8, 0.3, 0.2)).with_roughness(0.6).with_clearcoat(0.5, 0.4).with_sheen(0.5);
+        check_evaluate(Rc::new(plastic));
+        let metal = Principled::new(Color::new(0.9, 0.7, 0.3)).with_metallic(0.7).with_roughness(0.5).with_transmission(0.5, 1.5);
+        check_evaluate(Rc::new(metal));
+    }
+
+    #[test]
+    fn evaluates_coated() {
+        let base = Rc::new(Metal::new(Color::new(0.5, 0.6, 0.7), 0.8));
+        check_evaluate(Rc::new(Coated::new(base, 1.5).with_roughness(0.5).with_tint(Color::new(0.9, 0.8, 0.6))));
+    }
+
+    #[test]
+    fn lifts_coat_tint_for_spectral_bases() {
+        let mut rng = SmallRng::seed_from_u64(2);
+        let wo = Vec3::new(0.0, 1.0, 0.0);
+        let ray = Ray::new(wo, -wo).with_wavelengths(Some(SampledWavelengths::sample(&mut rng)));
+        let base = Rc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.0).with_thin_film(ThinFilm::new(300.0, 1.5)));
+        let coated: Rc<dyn Material> = Rc::new(Coated::new(base.clone(), 1.5).with_tint(Color::new(0.9, 0.2, 0.1)));
+        let hit_record = HitRecord::new(&Vec3::new(0.0, 0.0, 0.0), &ray, 1.0, &wo, coated.clone());
+
+        // Straight in and out goes through the coat twice.
+        let under = base.scatter(&ray, &hit_record, &mut rng).unwrap();
+        let tint = ray.wavelengths().unwrap().lift_rgb(&Color::new(0.81, 0.04, 0.01));
+        let expected = *under.attenuation() * tint;
+        let result = std::iter::repeat_with(|| coated.scatter(&ray, &hit_record, &mut rng).unwrap()).find(|result| result.spectral()).unwrap();
+        assert!((*result.attenuation() - expected).len() < 1e-9);
+    }
+
+    #[test]
+    fn leaves_mirrors_to_scattered_rays() {
+        let wo = Vec3::new(0.0, 1.0, 0.0);
+        let ray = Ray::new(wo, -wo);
+        let mirror: Rc<dyn Material> = Rc::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.0));
+        let hit_record = HitRecord::new(&Vec3::new(0.0, 0.0, 0.0), &ray, 1.0, &wo, mirror.clone());
+        assert!(mirror.evaluate(&ray, &hit_record, &wo).is_none());
+        assert!(!mirror.scatter(&ray, &hit_record, &mut SmallRng::seed_from_u64(0)).unwrap().evaluated());
+    }
+}
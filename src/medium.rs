@@ -0,0 +1,104 @@
+// Homogeneous participating media: the insides of objects where light scatters and is
+// absorbed along the way instead of only at surfaces.
+//
+// Coefficients are per color channel (or per sampled wavelength in spectral mode), in units
+// of 1 / world distance. Distances are sampled from one randomly chosen channel and all
+// channels are reweighted by the average pdf over the channels (the "spectral MIS" of
+// Wilkie et al. 2014), so that media with strongly colored coefficients stay unbiased.
+
+use crate::color::Color;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::util::random;
+use crate::vec3::Vec3;
+
+pub struct Medium {
+    sigma_a: Color,  // Absorption coefficient
+    sigma_s: Color,  // Scattering coefficient
+    g: f64,  // Henyey-Greenstein asymmetry, from -1 (backward) through 0 (isotropic) to 1 (forward)
+}
+
+pub enum MediumSample {
+    // The ray scattered inside the medium and continues as `scattered`.
+    Scatter { scattered: Ray, weight: Color },
+    // The ray made it through to the surface.
+    Pass { weight: Color },
+}
+
+impl Medium {
+    pub fn new(sigma_a: Color, sigma_s: Color, g: f64) -> Self {
+        let non_negative = |c: Color| Color::new(c.x().max(0.0), c.y().max(0.0), c.z().max(0.0));
+        Self {
+            sigma_a: non_negative(sigma_a),
+            sigma_s: non_negative(sigma_s),
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    // Artist-friendly parameters: `albedo` is the fraction of each interaction that scatters
+    // rather than absorbs, and `mean_free_path` the average distance between interactions.
+    pub fn from_albedo(albedo: Color, mean_free_path: Color, g: f64) -> Self {
+        let sigma_t = |mfp: f64| if mfp > 0.0 { 1.0 / mfp } else { 0.0 };
+        let sigma_t = Color::new(sigma_t(mean_free_path.x()), sigma_t(mean_free_path.y()), sigma_t(mean_free_path.z()));
+        let albedo = Color::new(albedo.x().clamp(0.0, 1.0), albedo.y().clamp(0.0, 1.0), albedo.z().clamp(0.0, 1.0));
+        Medium::new((Color::new(1.0, 1.0, 1.0) - albedo) * sigma_t, albedo * sigma_t, g)
+    }
+
+    // Samples where `ray` next interacts with the medium, given that it reaches the
+    // surface after `distance`.
+    pub fn sample(&self, ray: &Ray, distance: f64, rng: &mut dyn rand::RngCore) -> MediumSample {
+        let (sigma_a, sigma_s) = match ray.wavelengths() {
+            Some(wavelengths) => (wavelengths.lift_rgb(&self.sigma_a), wavelengths.lift_rgb(&self.sigma_s)),
+            None => (self.sigma_a, self.sigma_s),
+        };
+        let sigma_t = sigma_a + sigma_s;
+        let sigma_t = [sigma_t.x(), sigma_t.y(), sigma_t.z()];
+
+        let channel = random(0.0, 3.0, rng).min(2.0) as usize;
+        let t = if sigma_t[channel] > 0.0 {
+            -(1.0 - random(0.0, 1.0, rng)).ln() / sigma_t[channel]
+        } else {
+            f64::INFINITY
+        };
+
+        let transmittance = |t: f64| [(-sigma_t[0] * t).exp(), (-sigma_t[1] * t).exp(), (-sigma_t[2] * t).exp()];
+        if t < distance {
+            let tr = transmittance(t);
+            let pdf = (sigma_t[0] * tr[0] + sigma_t[1] * tr[1] + sigma_t[2] * tr[2]) / 3.0;
+            if pdf <= 0.0 {
+                return MediumSample::Pass { weight: Color::new(0.0, 0.0, 0.0) };
+            }
+            let unit_direction = ray.dir().unit_vec();
+            let scattered = Ray::new(*ray.orig() + t * unit_direction, self.sample_phase(&unit_direction, rng));
+            MediumSample::Scatter {
+                scattered: scattered,
+                weight: Color::new(tr[0], tr[1], tr[2]) * sigma_s / pdf,
+            }
+        } else {
+            let tr = transmittance(distance);
+            let pdf = (tr[0] + tr[1] + tr[2]) / 3.0;
+            if pdf <= 0.0 {
+                return MediumSample::Pass { weight: Color::new(0.0, 0.0, 0.0) };
+            }
+            MediumSample::Pass {
+                weight: Color::new(tr[0], tr[1], tr[2]) / pdf,
+            }
+        }
+    }
+
+    // Samples a new direction from the Henyey-Greenstein phase function. Since the samples
+    // follow the phase function exactly, the weight is 1.
+    fn sample_phase(&self, direction: &Vec3, rng: &mut dyn rand::RngCore) -> Vec3 {
+        let xi = random(0.0, 1.0, rng);
+        let cos_theta = if self.g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let s = (1.0 - self.g * self.g) / (1.0 - self.g + 2.0 * self.g * xi);
+            (1.0 + self.g * self.g - s * s) / (2.0 * self.g)
+        };
+        let cos_theta = cos_theta.clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = random(0.0, 2.0 * std::f64::consts::PI, rng);
+        Onb::new(direction).to_world(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+    }
+}
@@ -0,0 +1,107 @@
+// The GGX (Trowbridge-Reitz) microfacet distribution with the Smith shadowing-masking
+// term, shared by the rough specular materials.
+
+use crate::onb::Onb;
+use crate::util::random;
+use crate::vec3::Vec3;
+
+// Below this the distribution is so narrow that sampling it becomes numerically unstable.
+const MIN_ALPHA: f64 = 1e-3;
+
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    // `roughness` is the perceptual roughness in [0, 1]. The distribution's width is its
+    // square, which makes the parameter feel roughly linear.
+    pub fn new(roughness: f64) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Self {
+            alpha: (roughness * roughness).max(MIN_ALPHA),
+        }
+    }
+
+    // Samples a microfacet normal proportionally to D(h) * cos(theta_h) around `normal`.
+    pub fn sample_half_vector(&self, normal: &Vec3, rng: &mut dyn rand::RngCore) -> Vec3 {
+        let xi = random(0.0, 1.0, rng);
+        let phi = random(0.0, 2.0 * std::f64::consts::PI, rng);
+        let tan_sq_theta = self.alpha * self.alpha * xi / (1.0 - xi);
+        let cos_theta = 1.0 / (1.0 + tan_sq_theta).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        Onb::new(normal).to_world(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+    }
+
+    // Distributions narrower than this are left out of `Material::evaluate`. Light sampling
+    // would only find their highlights by chance, so they're found by scattered rays instead.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < 0.01
+    }
+
+    // Density of microfacet normals `h` around `normal`, D(h).
+    fn distribution(&self, h: &Vec3, normal: &Vec3) -> f64 {
+        let cos = h.dot(normal);
+        if cos <= 0.0 {
+            return 0.0;
+        }
+        let cos_sq = cos * cos;
+        let tan_sq = (1.0 - cos_sq) / cos_sq;
+        let alpha_sq = self.alpha * self.alpha;
+        alpha_sq / (std::f64::consts::PI * cos_sq * cos_sq * (alpha_sq + tan_sq) * (alpha_sq + tan_sq))
+    }
+
+    // Mirror reflection off the microfacets from `wi` to `wo`, excluding the Fresnel term:
+    // the BRDF times the cosine of `wi`, D * G / (4 * cos_o). All vectors must be normalized.
+    pub fn reflection(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> f64 {
+        let cos_o = wo.dot(normal);
+        let cos_i = wi.dot(normal);
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return 0.0;
+        }
+        let h = (*wo + *wi).unit_vec();
+        self.distribution(&h, normal) * self.g1(cos_o) * self.g1(cos_i) / (4.0 * cos_o)
+    }
+
+    // Probability density per unit solid angle of reflecting `wo` into `wi` off a half
+    // vector from `sample_half_vector`.
+    pub fn reflection_pdf(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> f64 {
+        let h = *wo + *wi;
+        if h.is_near_zero() {
+            return 0.0;
+        }
+        let h = h.unit_vec();
+        let cos_o = wo.dot(&h);
+        if cos_o <= 0.0 {
+            return 0.0;
+        }
+        self.distribution(&h, normal) * h.dot(normal) / (4.0 * cos_o)
+    }
+
+    // Smith masking for a direction at angle theta from the normal, given cos(theta).
+    fn g1(&self, cos_theta: f64) -> f64 {
+        let cos_sq = cos_theta * cos_theta;
+        if cos_sq <= 0.0 {
+            return 0.0;
+        }
+        let tan_sq = (1.0 - cos_sq) / cos_sq;
+        2.0 / (1.0 + (1.0 + self.alpha * self.alpha * tan_sq).sqrt())
+    }
+
+    // Weight of a direction `wi` scattered off the half vector `h`, where `h` came from
+    // `sample_half_vector`. Works for both reflection and refraction (Walter et al. 2007,
+    // eq. 41), and excludes the Fresnel term. All vectors must be normalized and `wo`
+    // points away from the surface.
+    pub fn sample_weight(&self, wo: &Vec3, wi: &Vec3, h: &Vec3, normal: &Vec3) -> f64 {
+        let cos_o = wo.dot(normal).abs();
+        let cos_h = h.dot(normal).abs();
+        if cos_o <= 0.0 || cos_h <= 0.0 {
+            return 0.0;
+        }
+        self.g1(cos_o) * self.g1(wi.dot(normal).abs()) * wo.dot(h).abs() / (cos_o * cos_h)
+    }
+}
+
+// Schlick's approximation to the Fresnel reflectance for a scalar normal-incidence value.
+pub fn schlick(f0: f64, cos: f64) -> f64 {
+    f0 + (1.0 - f0) * (1.0 - cos).clamp(0.0, 1.0).powi(5)
+}
@@ -0,0 +1,222 @@
+// Primary sample space Metropolis light transport (Kelemen et al. 2002), after the structure
+// of pbrt. The path tracer is driven by a sampler that hands out a vector of numbers in
+// [0, 1) instead of fresh random ones, so each vector stands for one path. Markov chains
+// wander over these vectors, mostly by nudging every number a little (small steps), sometimes
+// by starting afresh (large steps), and spend time on each path in proportion to its
+// brightness. Once a chain finds light that is hard to reach, such as through a small
+// opening, it explores the paths around it instead of losing them.
+//
+// The chains only give the relative brightness of the pixels. A bootstrap phase of ordinary
+// paths estimates the brightness of the whole image to scale them by, and picks where the
+// chains start.
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::film::Film;
+use crate::scene::Scene;
+use crate::util::random;
+
+use rand::SeedableRng;
+use rand::rngs::SmallRng;
+
+// Chance that a mutation starts a fresh path rather than nudging the current one.
+const LARGE_STEP_PROBABILITY: f64 = 0.3;
+// Standard deviation of the nudges of a small step.
+const SIGMA: f64 = 0.01;
+
+struct PrimarySample {
+    value: f64,
+    last_modified: u64,  // Iteration in which `value` last changed
+    // State before the current iteration's mutation, restored if it is rejected.
+    value_backup: f64,
+    last_modified_backup: u64,
+}
+
+// A random number generator that replays a vector of primary samples and mutates it on each
+// iteration. Numbers are only mutated when asked for, so a path that draws few of them
+// doesn't pay for the rest of the vector.
+struct MltSampler {
+    rng: SmallRng,  // For the mutations themselves
+    samples: Vec<PrimarySample>,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,  // Iteration of the last accepted large step
+    index: usize,  // Next sample handed out in this iteration
+}
+
+impl MltSampler {
+    // Samplers with the same seed replay the same initial vector.
+    fn new(seed: u64) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(seed),
+            samples: vec![],
+            iteration: 0,
+            large_step: true,
+            last_large_step: 0,
+            index: 0,
+        }
+    }
+
+    fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = random(0.0, 1.0, &mut self.rng) < LARGE_STEP_PROBABILITY;
+        self.index = 0;
+    }
+
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    fn reject(&mut self) {
+        for sample in self.samples.iter_mut() {
+            if sample.last_modified == self.iteration {
+                sample.value = sample.value_backup;
+                sample.last_modified = sample.last_modified_backup;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    fn next(&mut self) -> f64 {
+        if self.index == self.samples.len() {
+            // A path longer than any before it gets fresh numbers for the rest of its length.
+            let value = random(0.0, 1.0, &mut self.rng);
+            self.samples.push(PrimarySample {
+                value: value,
+                last_modified: self.iteration,
+                value_backup: value,
+                last_modified_backup: self.iteration.saturating_sub(1),
+            });
+            self.index += 1;
+            return value;
+        }
+        let sample = &mut self.samples[self.index];
+        self.index += 1;
+
+        // A sample last changed before the last accepted large step was replaced by it.
+        if sample.last_modified < self.last_large_step {
+            sample.value = random(0.0, 1.0, &mut self.rng);
+            sample.last_modified = self.last_large_step;
+        }
+        sample.value_backup = sample.value;
+        sample.last_modified_backup = sample.last_modified;
+
+        if self.large_step {
+            sample.value = random(0.0, 1.0, &mut self.rng);
+        } else if sample.last_modified < self.iteration {
+            // Small steps the sample missed while unused add up to one wider step.
+            let steps = (self.iteration - sample.last_modified) as f64;
+            sample.value += normal(&mut self.rng) * SIGMA * steps.sqrt();
+            sample.value -= sample.value.floor();
+        }
+        sample.last_modified = self.iteration;
+        sample.value
+    }
+}
+
+impl rand::RngCore for MltSampler {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    // Random floats are built from the high bits, so they come out as the primary sample.
+    fn next_u64(&mut self) -> u64 {
+        (self.next() * 18446744073709551616.0) as u64
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        rand::rand_core::impls::fill_bytes_via_next(self, dst)
+    }
+}
+
+// Standard normal sample, by the Box-Muller transform.
+fn normal(rng: &mut dyn rand::RngCore) -> f64 {
+    let u = 1.0 - random(0.0, 1.0, rng);
+    let v = random(0.0, 1.0, rng);
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+// What the chains try to spend time in proportion to.
+fn brightness(color: &Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+// Traces the path the sampler's numbers describe, returning where it lands on the image and
+// the light it carries.
+fn trace(camera: &Camera, scene: &Scene, sampler: &mut MltSampler) -> ((f64, f64), Color) {
+    let x = random(0.0, camera.image_width() as f64, sampler);
+    let y = random(0.0, camera.image_height() as f64, sampler);
+    let ray = camera.get_ray_at(x, y, sampler);
+    let color = camera.compute_ray_color(&ray, 0, scene, true, None, sampler);
+    // Paths that blow up would otherwise make chains stall on them.
+    if color.x().is_finite() && color.y().is_finite() && color.z().is_finite() {
+        ((x, y), color)
+    } else {
+        ((x, y), Color::new(0.0, 0.0, 0.0))
+    }
+}
+
+// Renders the image with `camera.samples_per_pixel()` mutations per pixel, shared out over
+// `camera.mlt_chains()` chains started from `camera.mlt_bootstrap_samples()` paths.
+pub fn render(camera: &Camera, scene: &Scene, rng: &mut dyn rand::RngCore) -> Film {
+    let width = camera.image_width();
+    let height = camera.image_height();
+    let mut film = Film::new(width, height, camera.samples_per_pixel());
+
+    // Bootstrap with ordinary paths. Each is traced by a sampler seeded with its index, so a
+    // chain can start from it again.
+    let bootstrap_samples = camera.mlt_bootstrap_samples().max(1) as u64;
+    let weights: Vec<f64> = (0..bootstrap_samples).map(|seed| brightness(&trace(camera, scene, &mut MltSampler::new(seed)).1)).collect();
+    let total_weight: f64 = weights.iter().sum();
+    let image_brightness = total_weight / bootstrap_samples as f64;
+    if total_weight <= 0.0 {
+        return film;
+    }
+
+    let mutations = camera.samples_per_pixel() as u64 * width as u64 * height as u64;
+    let chains = (camera.mlt_chains() as u64).clamp(1, mutations.max(1));
+    let mutations_per_chain = mutations.div_ceil(chains);
+    for chain in 0..chains {
+        let chains_remaining = chains - chain;
+        if chains_remaining.is_multiple_of(10) {
+            eprintln!("Chains remaining: {}", chains_remaining);
+        }
+
+        // Start from a bootstrap path picked in proportion to its brightness.
+        let mut target = random(0.0, total_weight, rng);
+        let seed = weights.iter().position(|&weight| {
+            target -= weight;
+            target < 0.0
+        }).unwrap_or(weights.len() - 1);
+        let mut sampler = MltSampler::new(seed as u64);
+        let (mut position, mut color) = trace(camera, scene, &mut sampler);
+
+        for _ in 0..mutations_per_chain.min(mutations.saturating_sub(chain * mutations_per_chain)) {
+            sampler.start_iteration();
+            let (proposed_position, proposed_color) = trace(camera, scene, &mut sampler);
+            let current_brightness = brightness(&color);
+            let proposed_brightness = brightness(&proposed_color);
+            let acceptance = if current_brightness > 0.0 { (proposed_brightness / current_brightness).min(1.0) } else { 1.0 };
+
+            // Both paths are recorded, weighted by the chance of moving to the proposal, which
+            // cuts the noise from rejected mutations.
+            if acceptance > 0.0 && proposed_brightness > 0.0 {
+                film.add_splat(proposed_position.0, proposed_position.1, &(proposed_color * (image_brightness * acceptance / proposed_brightness)));
+            }
+            if acceptance < 1.0 && current_brightness > 0.0 {
+                film.add_splat(position.0, position.1, &(color * (image_brightness * (1.0 - acceptance) / current_brightness)));
+            }
+
+            if random(0.0, 1.0, rng) < acceptance {
+                position = proposed_position;
+                color = proposed_color;
+                sampler.accept();
+            } else {
+                sampler.reject();
+            }
+        }
+    }
+    film
+}
@@ -0,0 +1,218 @@
+// Materials that perturb the shading normal before handing the hit to another material,
+// adding surface detail without extra geometry.
+
+use crate::color::Color;
+use crate::hit::HitRecord;
+use crate::material::{Material, ScatterResult, next_id};
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+
+use std::rc::Rc;
+
+// Minimum cosine between the viewer and the shading normal. Below this the normal is bent
+// back towards the viewer, otherwise the viewer would see the back of the surface.
+const MIN_VIEW_COSINE: f64 = 1e-3;
+
+// Step in (u, v) for the finite differences of bump maps.
+const BUMP_DELTA: f64 = 1e-4;
+
+// Tangent-space normal map. The texture's color encodes a normal as (color * 2 - 1), with
+// x along dp/du, y along the bitangent and z along the surface normal, so (0.5, 0.5, 1.0)
+// leaves the surface unchanged.
+pub struct NormalMap {
+    base: Rc<dyn Material>,
+    map: Rc<dyn Texture>,
+    strength: f64,  // Scales the tangent-plane part of the mapped normal
+    id: u64,  // See `next_id`
+}
+
+impl NormalMap {
+    pub fn new(base: Rc<dyn Material>, map: Rc<dyn Texture>, strength: f64) -> Self {
+        Self {
+            base: base,
+            map: map,
+            strength: strength.max(0.0),
+            id: next_id(),
+        }
+    }
+}
+
+impl NormalMap {
+    // The mapped normal, or None where the map leaves the surface unchanged.
+    fn mapped_normal(&self, hit_record: &HitRecord) -> Option<Vec3> {
+        let normal = outward_normal(hit_record);
+        let tangent = *hit_record.dpdu() - normal * normal.dot(hit_record.dpdu());
+        if tangent.is_near_zero() {
+            return None;
+        }
+        let tangent = tangent.unit_vec();
+        let bitangent = normal.cross(&tangent);
+
+        let color = self.map.value(hit_record.u(), hit_record.v(), hit_record.point());
+        let mapped = Vec3::new(
+            self.strength * (2.0 * color.x() - 1.0),
+            self.strength * (2.0 * color.y() - 1.0),
+            (2.0 * color.z() - 1.0).max(0.0),
+        );
+        let shading_normal = mapped.x() * tangent + mapped.y() * bitangent + mapped.z() * normal;
+        if shading_normal.is_near_zero() {
+            return None;
+        }
+        Some(shading_normal.unit_vec())
+    }
+}
+
+impl Material for NormalMap {
+    fn id(&self) -> u64 { self.id }
+
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
+        match self.mapped_normal(hit_record) {
+            Some(shading_normal) => scatter_with_shading_normal(self.base.as_ref(), ray, hit_record, &shading_normal, rng),
+            None => self.base.scatter(ray, hit_record, rng),
+        }
+    }
+
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Option<Color> {
+        match self.mapped_normal(hit_record) {
+            Some(shading_normal) => evaluate_with_shading_normal(self.base.as_ref(), ray, hit_record, &shading_normal, direction),
+            None => self.base.evaluate(ray, hit_record, direction),
+        }
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        match self.mapped_normal(hit_record) {
+            Some(shading_normal) => self.base.pdf(ray, &shading_record(ray, hit_record, &shading_normal), direction),
+            None => self.base.pdf(ray, hit_record, direction),
+        }
+    }
+
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        self.mapped_normal(hit_record).unwrap_or_else(|| self.base.shading_normal(hit_record))
+    }
+}
+
+// Scalar bump map. The surface is treated as displaced along its normal by the texture's
+// average channel times `scale`, and the shading normal is taken from the displaced surface.
+pub struct BumpMap {
+    base: Rc<dyn Material>,
+    height: Rc<dyn Texture>,
+    scale: f64,  // World space displacement for a height of 1
+    id: u64,  // See `next_id`
+}
+
+impl BumpMap {
+    pub fn new(base: Rc<dyn Material>, height: Rc<dyn Texture>, scale: f64) -> Self {
+        Self {
+            base: base,
+            height: height,
+            scale: scale,
+            id: next_id(),
+        }
+    }
+
+    fn height_at(&self, u: f64, v: f64, point: &Vec3) -> f64 {
+        let value = self.height.value(u, v, point);
+        (value.x() + value.y() + value.z()) / 3.0
+    }
+}
+
+impl BumpMap {
+    // The normal of the displaced surface, or None where it is degenerate.
+    fn bumped_normal(&self, hit_record: &HitRecord) -> Option<Vec3> {
+        let (u, v, point) = (hit_record.u(), hit_record.v(), hit_record.point());
+        let normal = outward_normal(hit_record);
+
+        // Forward differences of the height. The shifted lookups move the point along the
+        // tangents too, so spatial textures see the same step as (u, v) ones.
+        let height = self.height_at(u, v, point);
+        let dhdu = (self.height_at(u + BUMP_DELTA, v, &(*point + BUMP_DELTA * *hit_record.dpdu())) - height) / BUMP_DELTA;
+        let dhdv = (self.height_at(u, v + BUMP_DELTA, &(*point + BUMP_DELTA * *hit_record.dpdv())) - height) / BUMP_DELTA;
+
+        // Derivatives of the displaced surface p + scale * h * n, ignoring how n itself varies.
+        let dpdu = *hit_record.dpdu() + self.scale * dhdu * normal;
+        let dpdv = *hit_record.dpdv() + self.scale * dhdv * normal;
+        let shading_normal = dpdu.cross(&dpdv);
+        if shading_normal.is_near_zero() {
+            return None;
+        }
+        // The cross product follows the handedness of the (u, v) parameterization, which
+        // needn't match the outward normal.
+        let shading_normal = if shading_normal.dot(&normal) < 0.0 { -shading_normal } else { shading_normal };
+        Some(shading_normal.unit_vec())
+    }
+}
+
+impl Material for BumpMap {
+    fn id(&self) -> u64 { self.id }
+
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
+        match self.bumped_normal(hit_record) {
+            Some(shading_normal) => scatter_with_shading_normal(self.base.as_ref(), ray, hit_record, &shading_normal, rng),
+            None => self.base.scatter(ray, hit_record, rng),
+        }
+    }
+
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Option<Color> {
+        match self.bumped_normal(hit_record) {
+            Some(shading_normal) => evaluate_with_shading_normal(self.base.as_ref(), ray, hit_record, &shading_normal, direction),
+            None => self.base.evaluate(ray, hit_record, direction),
+        }
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        match self.bumped_normal(hit_record) {
+            Some(shading_normal) => self.base.pdf(ray, &shading_record(ray, hit_record, &shading_normal), direction),
+            None => self.base.pdf(ray, hit_record, direction),
+        }
+    }
+
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        self.bumped_normal(hit_record).unwrap_or_else(|| self.base.shading_normal(hit_record))
+    }
+}
+
+fn outward_normal(hit_record: &HitRecord) -> Vec3 {
+    if hit_record.front_face() { *hit_record.geometric_normal() } else { -*hit_record.geometric_normal() }
+}
+
+// The hit record with the given shading normal, bent towards the viewer if the viewer
+// would otherwise be behind it.
+fn shading_record(ray: &Ray, hit_record: &HitRecord, shading_normal: &Vec3) -> HitRecord {
+    let hit_record = hit_record.with_shading_normal(shading_normal);
+    let wo = -ray.dir().unit_vec();
+    let cos_view = wo.dot(hit_record.normal());
+    if cos_view < MIN_VIEW_COSINE {
+        let bent = *hit_record.normal() + (MIN_VIEW_COSINE - cos_view) * wo;
+        hit_record.with_shading_normal(&bent.unit_vec())
+    } else {
+        hit_record
+    }
+}
+
+// Scatters off `base` with the given shading normal, guarding against the two ways a
+// shading normal can disagree with the real surface:
+//   * The viewer is behind the shading normal. The normal is bent towards the viewer.
+//   * A reflected ray heads into the surface. It is mirrored back above the geometric
+//     surface. Rays that go below the shading normal too are refractions and are kept.
+fn scatter_with_shading_normal(base: &dyn Material, ray: &Ray, hit_record: &HitRecord, shading_normal: &Vec3, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
+    let hit_record = shading_record(ray, hit_record, shading_normal);
+    let result = base.scatter(ray, &hit_record, rng)?;
+    let direction = *result.scattered().dir();
+    let geometric_normal = hit_record.geometric_normal();
+    if direction.dot(geometric_normal) < 0.0 && direction.dot(hit_record.normal()) > 0.0 {
+        let mirrored = direction - 2.0 * direction.dot(geometric_normal) * *geometric_normal;
+        return Some(result.with_direction(mirrored));
+    }
+    Some(result)
+}
+
+// Evaluates `base` with the given shading normal. Light from below the geometric surface
+// can't reach the viewer however the shading normal leans.
+fn evaluate_with_shading_normal(base: &dyn Material, ray: &Ray, hit_record: &HitRecord, shading_normal: &Vec3, direction: &Vec3) -> Option<Color> {
+    let value = base.evaluate(ray, &shading_record(ray, hit_record, shading_normal), direction)?;
+    if direction.dot(hit_record.geometric_normal()) <= 0.0 {
+        return Some(Color::new(0.0, 0.0, 0.0));
+    }
+    Some(value)
+}
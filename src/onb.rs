@@ -0,0 +1,36 @@
+// An orthonormal basis built around a direction, used to sample directions in a local
+// frame where `w` points "up" (usually along a surface normal).
+
+use crate::vec3::Vec3;
+
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    // `n` doesn't need to be normalized, but must not be zero.
+    pub fn new(n: &Vec3) -> Self {
+        let w = n.unit_vec();
+        // Any vector that isn't parallel to w will do to start the cross products.
+        let a = if w.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = w.cross(&a).unit_vec();
+        let u = v.cross(&w);
+        Self {
+            u: u,
+            v: v,
+            w: w,
+        }
+    }
+
+    // Maps coordinates in this basis to world space.
+    pub fn to_world(&self, a: &Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    // Maps a world space vector to coordinates in this basis.
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
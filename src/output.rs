@@ -0,0 +1,428 @@
+// Writing the rendered image and its AOVs to disk, or the image alone to stdout. The
+// linear colors the renderer computes in its working color space are converted to the
+// output color space, along with the AOVs that hold colors. For PPM and PNG the image is
+// tone mapped in linear sRGB on the way, then encoded with the output space's transfer
+// function, dithered and quantized to 8 or 16 bits for viewing. PFM and OpenEXR keep the
+// linear radiance as floats, so that bright lights and highlights survive for later
+// exposure changes and compositing.
+
+use crate::aov;
+use crate::color::{Color, quantize};
+use crate::color_space::{ColorSpace, transform};
+use crate::dither::{self, Dither};
+use crate::png;
+use crate::tone_map::{ToneMapper, ToneMapping};
+
+use clap::ValueEnum;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ImageFormat {
+    // Binary Portable Pixmap (P6).
+    Ppm,
+    // Plain text Portable Pixmap (P3), three to four times larger.
+    PlainPpm,
+    // PNG, tagged with the output color space.
+    Png,
+    // Portable Float Map, 32-bit floats. AOVs go to files of their own, like with PPM.
+    Pfm,
+    // OpenEXR, uncompressed, with the AOVs as layers of the same file.
+    Exr,
+}
+
+impl ImageFormat {
+    // The format `path`'s extension asks for, if it names one.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "pfm" => Some(ImageFormat::Pfm),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+}
+
+pub struct ImageWriter {
+    format: ImageFormat,
+    half: bool,  // Whether OpenEXR channels are 16-bit floats instead of 32-bit ones
+    tone_mapping: ToneMapping,
+    working_space: ColorSpace,
+    output_space: ColorSpace,
+    sixteen_bit: bool,  // Whether PPM and PNG have 16 bits per channel instead of 8
+    dither: Dither,
+}
+
+impl ImageWriter {
+    pub fn new(format: ImageFormat) -> Self {
+        Self {
+            format: format,
+            half: false,
+            tone_mapping: ToneMapping::new(ToneMapper::Clamp, 0.0, None),
+            working_space: ColorSpace::Srgb,
+            output_space: ColorSpace::Srgb,
+            sixteen_bit: false,
+            dither: Dither::None,
+        }
+    }
+
+    pub fn with_half(mut self, half: bool) -> Self {
+        self.half = half;
+        self
+    }
+
+    // Only applies to the image, not the AOVs, and only in PPM and PNG. Float formats keep
+    // the radiance as rendered.
+    pub fn with_tone_mapping(mut self, tone_mapping: ToneMapping) -> Self {
+        self.tone_mapping = tone_mapping;
+        self
+    }
+
+    pub fn with_color_spaces(mut self, working_space: ColorSpace, output_space: ColorSpace) -> Self {
+        self.working_space = working_space;
+        self.output_space = output_space;
+        self
+    }
+
+    pub fn with_quantization(mut self, sixteen_bit: bool, dither: Dither) -> Self {
+        self.sixteen_bit = sixteen_bit;
+        self.dither = dither;
+        self
+    }
+
+    // Writes the `width` by `height` image `pixels`, linear colors row by row, to `out_file`,
+    // along with `aovs` given by name. An `out_file` of "-" means stdout, where AOVs only fit
+    // in OpenEXR.
+    pub fn write(&self, out_file: &str, width: u32, height: u32, pixels: &[Color], aovs: &[(&str, Vec<Color>)]) -> Result<()> {
+        let conversion = self.working_space.conversion_to(self.output_space);
+        let convert = |pixels: &[Color]| -> Vec<Color> { pixels.iter().map(|pixel| transform(&conversion, pixel)).collect() };
+        let aovs = self.convert_aovs(aovs);
+
+        if let ImageFormat::Exr = self.format {
+            let pixels = convert(pixels);
+            let mut layers = vec![("", pixels.as_slice())];
+            layers.extend(aovs.iter().map(|(name, pixels)| (*name, pixels.as_slice())));
+            let mut file = create(Path::new(out_file))?;
+            write_exr(&mut file, width, height, &layers, self.half, self.output_space)?;
+            return file.flush();
+        }
+
+        let pixels = if let ImageFormat::Pfm = self.format { convert(pixels) } else { self.tone_map(pixels) };
+        self.write_one(Path::new(out_file), width, height, &pixels)?;
+        for (name, pixels) in &aovs {
+            self.write_one(&aov_path(out_file, name), width, height, pixels)?;
+        }
+        Ok(())
+    }
+
+    // Converts the AOVs that hold colors to the output space, leaving normals, depths and
+    // object ids as they are.
+    fn convert_aovs<'a>(&self, aovs: &[(&'a str, Vec<Color>)]) -> Vec<(&'a str, Vec<Color>)> {
+        let conversion = self.working_space.conversion_to(self.output_space);
+        aovs.iter().map(|(name, pixels)| {
+            let pixels = if aov::is_color(name) { pixels.iter().map(|pixel| transform(&conversion, pixel)).collect() } else { pixels.clone() };
+            (*name, pixels)
+        }).collect()
+    }
+
+    // Tone maps working space colors and converts them to the output space. The tone mappers
+    // are tuned for linear sRGB primaries, from ACES's input matrix to the luminance weights,
+    // so the mapping happens there whatever the working and output spaces are.
+    fn tone_map(&self, pixels: &[Color]) -> Vec<Color> {
+        let to_srgb = self.working_space.conversion_to(ColorSpace::Srgb);
+        let from_srgb = ColorSpace::Srgb.conversion_to(self.output_space);
+        let srgb: Vec<Color> = pixels.iter().map(|pixel| transform(&to_srgb, pixel)).collect();
+        self.tone_mapping.apply(&srgb).iter().map(|pixel| transform(&from_srgb, pixel)).collect()
+    }
+
+    // Writes linear colors in the output space to a file of their own.
+    fn write_one(&self, path: &Path, width: u32, height: u32, pixels: &[Color]) -> Result<()> {
+        let mut file = create(path)?;
+        let max = if self.sixteen_bit { u16::MAX } else { u8::MAX as u16 };
+        let quantized = || -> Vec<u16> {
+            let noise = dither::noise(self.dither, width, height);
+            pixels.iter().zip(noise).flat_map(|(pixel, noise)| quantize(pixel, self.output_space, max, noise)).collect()
+        };
+        match self.format {
+            ImageFormat::Ppm => write_ppm(&mut file, width, height, max, &quantized())?,
+            ImageFormat::PlainPpm => write_plain_ppm(&mut file, width, height, max, &quantized())?,
+            ImageFormat::Png => png::write(&mut file, width, height, self.sixteen_bit, &quantized(), self.output_space)?,
+            ImageFormat::Pfm => write_pfm(&mut file, width, height, pixels)?,
+            ImageFormat::Exr => unreachable!("written with all layers in one file"),
+        }
+        file.flush()
+    }
+}
+
+// Opens `path` for writing, or stdout if it is "-".
+fn create(path: &Path) -> Result<BufWriter<Box<dyn Write>>> {
+    let file: Box<dyn Write> = if path == Path::new("-") { Box::new(io::stdout().lock()) } else { Box::new(File::create(path)?) };
+    Ok(BufWriter::new(file))
+}
+
+// Path of the AOV called `name`: "img/a.ppm" becomes "img/a.albedo.ppm".
+fn aov_path(out_file: &str, name: &str) -> PathBuf {
+    let path = Path::new(out_file);
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("ppm");
+    path.with_extension(format!("{}.{}", name, extension))
+}
+
+// `samples` holds the quantized channels of each pixel in turn, from 0 to `max`. Samples
+// above 255 take two bytes, most significant first.
+fn write_ppm(file: &mut dyn Write, width: u32, height: u32, max: u16, samples: &[u16]) -> Result<()> {
+    write!(file, "P6\n{} {}\n{}\n", width, height, max)?;
+    let data: Vec<u8> = if max > 255 {
+        samples.iter().flat_map(|sample| sample.to_be_bytes()).collect()
+    } else {
+        samples.iter().map(|&sample| sample as u8).collect()
+    };
+    file.write_all(&data)
+}
+
+fn write_plain_ppm(file: &mut dyn Write, width: u32, height: u32, max: u16, samples: &[u16]) -> Result<()> {
+    write!(file, "P3\n{} {}\n{}\n", width, height, max)?;
+    for pixel in samples.chunks(3) {
+        writeln!(file, "{} {} {}", pixel[0], pixel[1], pixel[2])?;
+    }
+    Ok(())
+}
+
+// PFM stores rows from the bottom up. The negative scale marks the floats as little endian.
+fn write_pfm(file: &mut dyn Write, width: u32, height: u32, pixels: &[Color]) -> Result<()> {
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+    for row in pixels.chunks(width as usize).rev() {
+        for pixel in row {
+            for value in [pixel.x(), pixel.y(), pixel.z()] {
+                file.write_all(&(value as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Writes a single part, scanline, uncompressed OpenEXR file. `layers` pairs each layer's
+// name with its pixels. The layer with the empty name has the channels R, G and B, and the
+// others have channels like albedo.R, which compositing tools group into layers. The colors
+// are linear in `space`, whose chromaticities go in the header.
+fn write_exr(file: &mut dyn Write, width: u32, height: u32, layers: &[(&str, &[Color])], half: bool, space: ColorSpace) -> Result<()> {
+    // Channels, each a layer and a component of its colors, sorted by name as the format
+    // requires.
+    let mut channels: Vec<(String, usize, usize)> = vec![];
+    for (layer, (name, _)) in layers.iter().enumerate() {
+        for (component, suffix) in ["R", "G", "B"].iter().enumerate() {
+            let channel = if name.is_empty() { suffix.to_string() } else { format!("{}.{}", name, suffix) };
+            channels.push((channel, layer, component));
+        }
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut channel_list = vec![];
+    for (name, _, _) in &channels {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&(if half { 1i32 } else { 2i32 }).to_le_bytes());  // HALF or FLOAT
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);  // pLinear and reserved
+        channel_list.extend_from_slice(&1i32.to_le_bytes());  // x sampling
+        channel_list.extend_from_slice(&1i32.to_le_bytes());  // y sampling
+    }
+    channel_list.push(0);
+
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|value| value.to_le_bytes()).collect();
+    let mut header = vec![];
+    let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(kind.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    };
+    let chromaticities: Vec<u8> = space.primaries().iter().chain([space.white()].iter())
+        .flat_map(|(x, y)| [(*x as f32).to_le_bytes(), (*y as f32).to_le_bytes()].concat())
+        .collect();
+    attribute("channels", "chlist", &channel_list);
+    attribute("chromaticities", "chromaticities", &chromaticities);
+    attribute("compression", "compression", &[0]);  // None
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    attribute("lineOrder", "lineOrder", &[0]);  // Increasing y
+    attribute("pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0.0f32.to_le_bytes(), 0.0f32.to_le_bytes()].concat());
+    attribute("screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+
+    // Names longer than 31 bytes need the long names flag.
+    let version: u32 = if channels.iter().any(|(name, _, _)| name.len() > 31) { 2 | 0x400 } else { 2 };
+    file.write_all(&[0x76, 0x2f, 0x31, 0x01])?;
+    file.write_all(&version.to_le_bytes())?;
+    file.write_all(&header)?;
+
+    // Each scanline is a block of its own, listed in a table of offsets from the start of the
+    // file.
+    let bytes_per_value = if half { 2 } else { 4 };
+    let data_size = width as usize * channels.len() * bytes_per_value;
+    let first_block = 8 + header.len() + 8 * height as usize;
+    for row in 0..height as usize {
+        file.write_all(&((first_block + row * (8 + data_size)) as u64).to_le_bytes())?;
+    }
+
+    let mut data = Vec::with_capacity(data_size);
+    for row in 0..height as usize {
+        data.clear();
+        for (_, layer, component) in &channels {
+            let pixels = &layers[*layer].1[row * width as usize..(row + 1) * width as usize];
+            for pixel in pixels {
+                let value = [pixel.x(), pixel.y(), pixel.z()][*component] as f32;
+                if half {
+                    data.extend_from_slice(&f32_to_half(value).to_le_bytes());
+                } else {
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        file.write_all(&(row as i32).to_le_bytes())?;
+        file.write_all(&(data_size as i32).to_le_bytes())?;
+        file.write_all(&data)?;
+    }
+    Ok(())
+}
+
+// Converts to the bits of the nearest 16-bit float. Values too large for it become infinity
+// and values too small become 0.
+fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity stays infinity, and NaN stays NaN.
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        // Subnormal, with the implicit leading 1 shifted into the mantissa.
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let rounding = (mantissa >> (shift - 1)) & 1;
+        return sign | ((mantissa >> shift) + rounding) as u16;
+    }
+    // Rounding may carry into the exponent, which is still the right result.
+    let rounding = (mantissa >> 12) & 1;
+    sign | ((((exponent as u32) << 10) | (mantissa >> 13)) + rounding) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_half() {
+        assert_eq!(f32_to_half(0.0), 0x0000);
+        assert_eq!(f32_to_half(1.0), 0x3c00);
+        assert_eq!(f32_to_half(-2.0), 0xc000);
+        assert_eq!(f32_to_half(65504.0), 0x7bff);
+        assert_eq!(f32_to_half(65536.0), 0x7c00);
+        assert_eq!(f32_to_half(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_half(f32::NAN) & 0x7c00, 0x7c00);
+        assert_ne!(f32_to_half(f32::NAN) & 0x3ff, 0);
+        assert_eq!(f32_to_half(1e-8), 0x0000);
+        // The smallest subnormal and the smallest normal.
+        assert_eq!(f32_to_half(2.0_f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_half(2.0_f32.powi(-14)), 0x0400);
+    }
+
+    fn read_u32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn read_f32(bytes: &[u8], at: usize) -> f32 {
+        f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn read_string(bytes: &[u8], at: usize) -> String {
+        let end = at + bytes[at..].iter().position(|&byte| byte == 0).unwrap();
+        String::from_utf8(bytes[at..end].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn writes_exr_layout() {
+        let pixels = [Color::new(0.25, 0.5, 2.0)];
+        let mut bytes = vec![];
+        write_exr(&mut bytes, 1, 1, &[("", &pixels)], false, ColorSpace::Srgb).unwrap();
+
+        assert_eq!(bytes[0..4], [0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(read_u32(&bytes, 4), 2);
+
+        // Attributes are a name, a type, a size and a value each, up to an empty name.
+        let mut at = 8;
+        let mut names = vec![];
+        while bytes[at] != 0 {
+            let name = read_string(&bytes, at);
+            at += name.len() + 1;
+            let kind = read_string(&bytes, at);
+            at += kind.len() + 1;
+            let size = read_u32(&bytes, at) as usize;
+            at += 4;
+            if name == "channels" {
+                assert_eq!(read_string(&bytes, at), "B");
+                assert_eq!(read_u32(&bytes, at + 2), 2);  // FLOAT
+                assert_eq!(size, 3 * (2 + 16) + 1);
+            }
+            if name == "dataWindow" {
+                assert_eq!(bytes[at..at + size], [0; 16]);
+            }
+            names.push(name);
+            at += size;
+        }
+        at += 1;
+        for required in ["channels", "compression", "dataWindow", "displayWindow", "lineOrder", "pixelAspectRatio", "screenWindowCenter", "screenWindowWidth"] {
+            assert!(names.iter().any(|name| name == required), "{} is missing", required);
+        }
+
+        // One offset, pointing just past the offset table at the only scanline.
+        let offset = u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize;
+        assert_eq!(offset, at + 8);
+        assert_eq!(read_u32(&bytes, offset), 0);  // y
+        assert_eq!(read_u32(&bytes, offset + 4), 12);  // Data size
+        assert_eq!([read_f32(&bytes, offset + 8), read_f32(&bytes, offset + 12), read_f32(&bytes, offset + 16)], [2.0, 0.5, 0.25]);
+        assert_eq!(bytes.len(), offset + 8 + 12);
+    }
+
+    #[test]
+    fn converts_only_color_aovs() {
+        let writer = ImageWriter::new(ImageFormat::Pfm).with_color_spaces(ColorSpace::Srgb, ColorSpace::Rec2020);
+        let red = Color::new(1.0, 0.0, 0.0);
+        let aovs = [("albedo", vec![red]), ("light-0", vec![red]), ("normal", vec![red]), ("depth", vec![red]), ("object-id", vec![red])];
+        let converted = writer.convert_aovs(&aovs);
+
+        let expected = transform(&ColorSpace::Srgb.conversion_to(ColorSpace::Rec2020), &red);
+        assert_ne!(expected, red);
+        for (name, pixels) in &converted {
+            assert_eq!(pixels[0], if aov::is_color(name) { expected } else { red }, "{}", name);
+        }
+        assert_eq!(converted.iter().filter(|(name, _)| aov::is_color(name)).count(), 2);
+    }
+
+    #[test]
+    fn writes_pfm_rows_bottom_up() {
+        let pixels = [Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.0, 1.0)];  // Top, bottom
+        let mut bytes = vec![];
+        write_pfm(&mut bytes, 1, 2, &pixels).unwrap();
+
+        let header = b"PF\n1 2\n-1.0\n";
+        assert_eq!(bytes[..header.len()], header[..]);
+        let values: Vec<f32> = bytes[header.len()..].chunks(4).map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap())).collect();
+        assert_eq!(values, [0.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,221 @@
+// A minimal PNG encoder. The pixel data is stored in uncompressed deflate blocks, which keeps
+// the encoder short at the cost of file size.
+//
+// The image is tagged with its color space so viewers show it correctly: sRGB images with
+// the sRGB chunk, and others with an ICC profile in the iCCP chunk. Both come with the cHRM
+// chunk, for viewers that only understand that.
+
+use crate::color_space::ColorSpace;
+
+use std::io::{Result, Write};
+
+// Writes a `width` by `height` RGB image encoded in `space`. `samples` holds the channels of
+// each pixel in turn, row by row, with 16 bits each if `sixteen_bit` is set and 8 otherwise.
+pub fn write(file: &mut dyn Write, width: u32, height: u32, sixteen_bit: bool, samples: &[u16], space: ColorSpace) -> Result<()> {
+    file.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut header = vec![];
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    let bit_depth = if sixteen_bit { 16 } else { 8 };
+    header.extend_from_slice(&[bit_depth, 2, 0, 0, 0]);  // RGB, deflate, no filter, no interlace
+    write_chunk(file, b"IHDR", &header)?;
+
+    let mut chromaticities = vec![];
+    let (white_x, white_y) = space.white();
+    for (x, y) in [(white_x, white_y)].iter().chain(space.primaries().iter()) {
+        chromaticities.extend_from_slice(&((x * 100_000.0).round() as u32).to_be_bytes());
+        chromaticities.extend_from_slice(&((y * 100_000.0).round() as u32).to_be_bytes());
+    }
+    if space == ColorSpace::Srgb {
+        write_chunk(file, b"sRGB", &[0])?;  // Perceptual rendering intent
+        write_chunk(file, b"gAMA", &45455u32.to_be_bytes())?;
+    } else {
+        let mut profile = vec![];
+        profile.extend_from_slice(space.name().as_bytes());
+        profile.extend_from_slice(&[0, 0]);  // Null terminator and deflate
+        profile.extend_from_slice(&zlib(&icc_profile(space)));
+        write_chunk(file, b"iCCP", &profile)?;
+    }
+    write_chunk(file, b"cHRM", &chromaticities)?;
+
+    // Each row starts with its filter type, which is always none. 16-bit samples are stored
+    // most significant byte first.
+    let row_size = 3 * width as usize;
+    let mut image = Vec::with_capacity((row_size * bit_depth as usize / 8 + 1) * height as usize);
+    for row in samples.chunks(row_size) {
+        image.push(0);
+        for &sample in row {
+            if sixteen_bit {
+                image.extend_from_slice(&sample.to_be_bytes());
+            } else {
+                image.push(sample as u8);
+            }
+        }
+    }
+    write_chunk(file, b"IDAT", &zlib(&image))?;
+    write_chunk(file, b"IEND", &[])
+}
+
+fn write_chunk(file: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    file.write_all(&(data.len() as u32).to_be_bytes())?;
+    file.write_all(kind)?;
+    file.write_all(data)?;
+    let crc = crc32(&[kind.as_slice(), data].concat());
+    file.write_all(&crc.to_be_bytes())
+}
+
+// Wraps `data` in a zlib stream of uncompressed deflate blocks.
+fn zlib(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 65535;
+    let mut stream = vec![0x78, 0x01];
+    let blocks = data.len().div_ceil(MAX_BLOCK).max(1);
+    for index in 0..blocks {
+        let block = &data[index * MAX_BLOCK..((index + 1) * MAX_BLOCK).min(data.len())];
+        stream.push(if index + 1 == blocks { 1 } else { 0 });  // Whether this is the final block
+        stream.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        stream.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// A version 2 ICC display profile for `space`: the primaries as XYZ colorants adapted to
+// D50, and the transfer function as a lookup table for each channel.
+fn icc_profile(space: ColorSpace) -> Vec<u8> {
+    fn fixed(value: f64) -> [u8; 4] {
+        ((value * 65536.0).round() as i32).to_be_bytes()
+    }
+    fn xyz(x: f64, y: f64, z: f64) -> Vec<u8> {
+        [b"XYZ \0\0\0\0".as_slice(), &fixed(x), &fixed(y), &fixed(z)].concat()
+    }
+
+    let matrix = space.rgb_to_d50_xyz();
+    let colorant = |column: usize| xyz(matrix[0][column], matrix[1][column], matrix[2][column]);
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    if space.decode(0.5) == 0.5 {
+        curve.extend_from_slice(&0u32.to_be_bytes());  // No entries means linear
+    } else {
+        const ENTRIES: u32 = 1024;
+        curve.extend_from_slice(&ENTRIES.to_be_bytes());
+        for index in 0..ENTRIES {
+            let value = space.decode(index as f64 / (ENTRIES - 1) as f64);
+            curve.extend_from_slice(&((value.clamp(0.0, 1.0) * 65535.0).round() as u16).to_be_bytes());
+        }
+    }
+    let mut description = b"desc\0\0\0\0".to_vec();
+    description.extend_from_slice(&(space.name().len() as u32 + 1).to_be_bytes());
+    description.extend_from_slice(space.name().as_bytes());
+    description.push(0);
+    description.extend_from_slice(&[0; 4 + 4 + 2 + 1 + 67]);  // Empty Unicode and ScriptCode descriptions
+    let mut copyright = b"text\0\0\0\0".to_vec();
+    copyright.extend_from_slice(b"No copyright, use freely\0");
+
+    // The three curves share their data.
+    let tags: [(&[u8; 4], usize); 9] = [(b"desc", 0), (b"cprt", 1), (b"wtpt", 2), (b"rXYZ", 3), (b"gXYZ", 4), (b"bXYZ", 5), (b"rTRC", 6), (b"gTRC", 6), (b"bTRC", 6)];
+    let data = [description, copyright, xyz(D50_XYZ.0, D50_XYZ.1, D50_XYZ.2), colorant(0), colorant(1), colorant(2), curve];
+
+    // Tag data follows the header and the tag table, each item aligned to 4 bytes.
+    let mut offsets = vec![];
+    let mut body = vec![];
+    let body_start = 128 + 4 + 12 * tags.len();
+    for item in &data {
+        offsets.push(body_start + body.len());
+        body.extend_from_slice(item);
+        body.resize(body.len().next_multiple_of(4), 0);
+    }
+    let size = body_start + body.len();
+
+    let mut profile = vec![];
+    profile.extend_from_slice(&(size as u32).to_be_bytes());
+    profile.extend_from_slice(&[0; 4]);  // Preferred CMM
+    profile.extend_from_slice(&[2, 0x10, 0, 0]);  // Version 2.1
+    profile.extend_from_slice(b"mntrRGB XYZ ");  // Display device, RGB data, XYZ connection space
+    profile.extend_from_slice(&[0; 12]);  // Date and time
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 24]);  // Platform, flags, manufacturer, model and attributes
+    profile.extend_from_slice(&[0; 4]);  // Perceptual rendering intent
+    profile.extend_from_slice(&[fixed(D50_XYZ.0), fixed(D50_XYZ.1), fixed(D50_XYZ.2)].concat());
+    profile.resize(128, 0);  // Creator and reserved bytes
+
+    profile.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+    for (signature, item) in tags {
+        profile.extend_from_slice(signature);
+        profile.extend_from_slice(&(offsets[item] as u32).to_be_bytes());
+        profile.extend_from_slice(&(data[item].len() as u32).to_be_bytes());
+    }
+    profile.extend_from_slice(&body);
+    profile
+}
+
+// The ICC profile connection space's white, D50, as XYZ.
+const D50_XYZ: (f64, f64, f64) = (0.9642, 1.0, 0.8249);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn splits_zlib_into_stored_blocks() {
+        let data: Vec<u8> = (0..150_000u32).map(|index| (index * 7 % 251) as u8).collect();
+        let stream = zlib(&data);
+        assert_eq!(stream[0..2], [0x78, 0x01]);
+
+        // Each block is a final flag, LEN and its complement NLEN, then LEN bytes of data.
+        let mut at = 2;
+        let mut lengths = vec![];
+        let mut inflated = vec![];
+        loop {
+            let last = stream[at];
+            let length = u16::from_le_bytes([stream[at + 1], stream[at + 2]]);
+            let complement = u16::from_le_bytes([stream[at + 3], stream[at + 4]]);
+            assert_eq!(complement, !length);
+            at += 5;
+            inflated.extend_from_slice(&stream[at..at + length as usize]);
+            at += length as usize;
+            lengths.push(length);
+            if last == 1 {
+                break;
+            }
+            assert_eq!(last, 0);
+        }
+        assert_eq!(lengths, [65535, 65535, 18930]);
+        assert_eq!(inflated, data);
+        assert_eq!(stream[at..], adler32(&data).to_be_bytes());
+    }
+
+    #[test]
+    fn sizes_icc_profile() {
+        let profile = icc_profile(ColorSpace::DisplayP3);
+        assert_eq!(u32::from_be_bytes(profile[0..4].try_into().unwrap()) as usize, profile.len());
+        assert_eq!(profile[36..40], *b"acsp");
+        assert_eq!(u32::from_be_bytes(profile[128..132].try_into().unwrap()), 9);
+    }
+}
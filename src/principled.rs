@@ -0,0 +1,354 @@
+// A "principled" uber material in the spirit of the Disney BSDF (Burley 2012). A single
+// set of intuitive parameters covers plastics, metals, glass and everything in between.
+//
+// Lobes are picked stochastically in proportion to how much light they carry, so each call
+// to `scatter` follows one lobe. The diffuse, sheen and rough GGX lobes can also be evaluated
+// for light sampling; the glass and near-mirror lobes are only found by scattered rays.
+
+use crate::color::Color;
+use crate::hit::HitRecord;
+use crate::material::{Material, ScatterResult, next_id};
+use crate::microfacet::{Ggx, schlick};
+use crate::ray::Ray;
+use crate::texture::{SolidColor, Texture};
+use crate::util::random;
+use crate::vec3::Vec3;
+
+use std::rc::Rc;
+
+// Normal-incidence reflectance of the clearcoat layer, which has a fixed IOR of 1.5.
+const CLEARCOAT_F0: f64 = 0.04;
+
+// Parameters are all in [0, 1] except the refractive index. Those that vary over a surface
+// are textures, and the scalar ones use the average of the texture's channels.
+pub struct Principled {
+    base_color: Rc<dyn Texture>,
+    metallic: Rc<dyn Texture>,
+    roughness: Rc<dyn Texture>,
+    specular: Rc<dyn Texture>,  // Reflectance of non-metals at normal incidence, where 0.5 means 4%
+    clearcoat: Rc<dyn Texture>,  // Strength of a glossy white layer on top of everything else
+    clearcoat_roughness: f64,
+    sheen: Rc<dyn Texture>,  // Extra grazing-angle reflection for cloth-like surfaces
+    transmission: Rc<dyn Texture>,  // How much of the non-metallic base is glass instead of diffuse
+    refractive_index: f64,  // Used for transmission
+    id: u64,  // See `next_id`
+}
+
+// The parameters looked up at a hit point.
+struct Parameters {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    clearcoat: f64,
+    sheen: f64,
+    transmission: f64,
+}
+
+// Each lobe's share of the light leaving in the outgoing direction, which is also how likely
+// `scatter` is to pick it. The shares add up to 1.
+struct Lobes {
+    clearcoat: f64,
+    metallic: f64,
+    plastic: f64,  // The specular coat and the diffuse base under it together
+    specular: f64,
+    diffuse: f64,
+    transmission: f64,
+}
+
+impl Principled {
+    // Defaults to a rough, white-ish plastic. Use the `with_*` methods to change the rest.
+    pub fn new(base_color: Color) -> Self {
+        Self::textured(Rc::new(SolidColor::new(base_color)))
+    }
+
+    pub fn textured(base_color: Rc<dyn Texture>) -> Self {
+        Self {
+            base_color: base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_roughness: 0.03,
+            sheen: constant(0.0),
+            transmission: constant(0.0),
+            refractive_index: 1.5,
+            id: next_id(),
+        }
+    }
+
+    pub fn with_metallic(self, metallic: f64) -> Self {
+        self.with_metallic_texture(constant(metallic))
+    }
+
+    pub fn with_metallic_texture(mut self, metallic: Rc<dyn Texture>) -> Self {
+        self.metallic = metallic;
+        self
+    }
+
+    pub fn with_roughness(self, roughness: f64) -> Self {
+        self.with_roughness_texture(constant(roughness))
+    }
+
+    pub fn with_roughness_texture(mut self, roughness: Rc<dyn Texture>) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn with_specular(self, specular: f64) -> Self {
+        self.with_specular_texture(constant(specular))
+    }
+
+    pub fn with_specular_texture(mut self, specular: Rc<dyn Texture>) -> Self {
+        self.specular = specular;
+        self
+    }
+
+    pub fn with_clearcoat(self, clearcoat: f64, clearcoat_roughness: f64) -> Self {
+        self.with_clearcoat_texture(constant(clearcoat), clearcoat_roughness)
+    }
+
+    pub fn with_clearcoat_texture(mut self, clearcoat: Rc<dyn Texture>, clearcoat_roughness: f64) -> Self {
+        self.clearcoat = clearcoat;
+        self.clearcoat_roughness = clearcoat_roughness.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_sheen(self, sheen: f64) -> Self {
+        self.with_sheen_texture(constant(sheen))
+    }
+
+    pub fn with_sheen_texture(mut self, sheen: Rc<dyn Texture>) -> Self {
+        self.sheen = sheen;
+        self
+    }
+
+    pub fn with_transmission(self, transmission: f64, refractive_index: f64) -> Self {
+        self.with_transmission_texture(constant(transmission), refractive_index)
+    }
+
+    pub fn with_transmission_texture(mut self, transmission: Rc<dyn Texture>, refractive_index: f64) -> Self {
+        self.transmission = transmission;
+        self.refractive_index = if refractive_index > 0.0 { refractive_index } else { 1.0 };
+        self
+    }
+
+    // Looks the parameters up at the hit point, clamping them into range.
+    fn parameters(&self, hit_record: &HitRecord) -> Parameters {
+        let value = |texture: &Rc<dyn Texture>| texture.value(hit_record.u(), hit_record.v(), hit_record.point());
+        let scalar = |texture: &Rc<dyn Texture>| {
+            let value = value(texture);
+            ((value.x() + value.y() + value.z()) / 3.0).clamp(0.0, 1.0)
+        };
+        Parameters {
+            base_color: value(&self.base_color),
+            metallic: scalar(&self.metallic),
+            roughness: scalar(&self.roughness),
+            specular: scalar(&self.specular),
+            clearcoat: scalar(&self.clearcoat),
+            sheen: scalar(&self.sheen),
+            transmission: scalar(&self.transmission),
+        }
+    }
+
+    // Splits the light leaving at `cos_o` to the normal between the lobes. The clearcoat takes
+    // its Fresnel share first and the rest is split by the parameters.
+    fn lobes(parameters: &Parameters, cos_o: f64) -> Lobes {
+        let clearcoat = parameters.clearcoat * schlick(CLEARCOAT_F0, cos_o);
+        let dielectric = (1.0 - clearcoat) * (1.0 - parameters.metallic);
+        let plastic = dielectric * (1.0 - parameters.transmission);
+        let specular = schlick(specular_f0(parameters), cos_o);
+        Lobes {
+            clearcoat: clearcoat,
+            metallic: (1.0 - clearcoat) * parameters.metallic,
+            plastic: plastic,
+            specular: plastic * specular,
+            diffuse: plastic * (1.0 - specular),
+            transmission: dielectric * parameters.transmission,
+        }
+    }
+
+    // The lobes that can be evaluated, as the BRDF times the cosine of `wi`, along with the
+    // density of `scatter` sampling `wi` from them. None if they carry no light.
+    fn evaluate_lobes(&self, parameters: &Parameters, lobes: &Lobes, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> Option<(Color, f64)> {
+        let clearcoat_ggx = Ggx::new(self.clearcoat_roughness);
+        let ggx = Ggx::new(parameters.roughness);
+        let clearcoat = lobes.clearcoat > 0.0 && !clearcoat_ggx.is_smooth();
+        let glossy = lobes.metallic + lobes.specular > 0.0 && !ggx.is_smooth();
+        if !clearcoat && !glossy && lobes.diffuse <= 0.0 {
+            return None;
+        }
+
+        let mut value = Color::new(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+        let cos_i = wi.dot(normal);
+        if cos_i <= 0.0 {
+            return Some((value, pdf));
+        }
+        let cos_h = wo.dot(&(*wo + *wi).unit_vec());
+        if clearcoat {
+            let reflection = parameters.clearcoat * schlick(CLEARCOAT_F0, cos_h) * clearcoat_ggx.reflection(wo, wi, normal);
+            value += Color::new(reflection, reflection, reflection);
+            pdf += lobes.clearcoat * clearcoat_ggx.reflection_pdf(wo, wi, normal);
+        }
+        if glossy {
+            let reflection = ggx.reflection(wo, wi, normal);
+            let specular = lobes.plastic * schlick(specular_f0(parameters), cos_h);
+            value += (reflection * lobes.metallic) * metal_fresnel(parameters, cos_h) + Color::new(1.0, 1.0, 1.0) * (reflection * specular);
+            pdf += (lobes.metallic + lobes.specular) * ggx.reflection_pdf(wo, wi, normal);
+        }
+        value += (lobes.diffuse * cos_i / std::f64::consts::PI) * diffuse_albedo(parameters, wo, wi, normal);
+        pdf += lobes.diffuse * cos_i / std::f64::consts::PI;
+        Some((value, pdf))
+    }
+
+    // A direction sampled from one of the lobes `evaluate_lobes` covers, weighted by all of
+    // them together.
+    fn evaluated(&self, parameters: &Parameters, lobes: &Lobes, wo: &Vec3, wi: Vec3, hit_record: &HitRecord) -> Option<ScatterResult> {
+        if wi.dot(hit_record.normal()) <= 0.0 {
+            return None;
+        }
+        let (value, pdf) = self.evaluate_lobes(parameters, lobes, wo, &wi, hit_record.normal())?;
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterResult::new(Ray::new(*hit_record.point(), wi), value / pdf).with_evaluated(true))
+    }
+
+    // Glossy reflection off a microfacet with normal `h`, for lobes too smooth to evaluate.
+    // Returns None when the reflected direction ends up below the surface.
+    fn reflect(ggx: &Ggx, wo: &Vec3, h: &Vec3, hit_record: &HitRecord, tint: Color) -> Option<ScatterResult> {
+        let wi = (-*wo).reflect(h);
+        if wi.dot(hit_record.normal()) <= 0.0 {
+            return None;
+        }
+        Some(ScatterResult::new(
+            Ray::new(*hit_record.point(), wi),
+            tint * ggx.sample_weight(wo, &wi, h, hit_record.normal()),
+        ))
+    }
+
+    // Rough glass: reflects or refracts through a sampled microfacet, choosing by Fresnel.
+    fn transmit(&self, parameters: &Parameters, wo: &Vec3, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
+        let ggx = Ggx::new(parameters.roughness);
+        let h = ggx.sample_half_vector(hit_record.normal(), rng);
+        let cos_o = wo.dot(&h);
+        if cos_o <= 0.0 {
+            return None;
+        }
+
+        let relative_refractive_index = if hit_record.front_face() { 1.0 / self.refractive_index } else { self.refractive_index };
+        let cannot_refract = relative_refractive_index * (1.0 - cos_o * cos_o).sqrt() > 1.0;
+        let r0 = (1.0 - relative_refractive_index) / (1.0 + relative_refractive_index);
+        if cannot_refract || schlick(r0 * r0, cos_o) > random(0.0, 1.0, rng) {
+            return Principled::reflect(&ggx, wo, &h, hit_record, Color::new(1.0, 1.0, 1.0));
+        }
+
+        let wi = Vec3::refract(&-*wo, &h, relative_refractive_index);
+        if wi.dot(hit_record.normal()) >= 0.0 {
+            return None;
+        }
+        // Tint once, on the way in, so light crossing the object picks up the base color once.
+        let tint = if hit_record.front_face() { parameters.base_color } else { Color::new(1.0, 1.0, 1.0) };
+        Some(ScatterResult::new(
+            Ray::new(*hit_record.point(), wi),
+            tint * ggx.sample_weight(wo, &wi, &h, hit_record.normal()),
+        ))
+    }
+}
+
+impl Material for Principled {
+    fn id(&self) -> u64 { self.id }
+
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut dyn rand::RngCore) -> Option<ScatterResult> {
+        let wo = -ray.dir().unit_vec();
+        let normal = hit_record.normal();
+        let parameters = self.parameters(hit_record);
+        let cos_o = wo.dot(normal);
+        let lobes = Principled::lobes(&parameters, cos_o);
+
+        let mut choice = random(0.0, 1.0, rng);
+        if choice < lobes.clearcoat {
+            let ggx = Ggx::new(self.clearcoat_roughness);
+            let h = ggx.sample_half_vector(normal, rng);
+            if ggx.is_smooth() {
+                let fresnel = schlick(CLEARCOAT_F0, wo.dot(&h)) / schlick(CLEARCOAT_F0, cos_o);
+                return Principled::reflect(&ggx, &wo, &h, hit_record, Color::new(fresnel, fresnel, fresnel));
+            }
+            return self.evaluated(&parameters, &lobes, &wo, (-wo).reflect(&h), hit_record);
+        }
+        choice -= lobes.clearcoat;
+
+        if choice < lobes.metallic + lobes.specular {
+            let ggx = Ggx::new(parameters.roughness);
+            let h = ggx.sample_half_vector(normal, rng);
+            if !ggx.is_smooth() {
+                return self.evaluated(&parameters, &lobes, &wo, (-wo).reflect(&h), hit_record);
+            }
+            // The two smooth lobes share the half vector but are weighted separately.
+            let tint = if choice < lobes.metallic {
+                metal_fresnel(&parameters, wo.dot(&h))
+            } else {
+                let fresnel = schlick(specular_f0(&parameters), wo.dot(&h)) / schlick(specular_f0(&parameters), cos_o);
+                Color::new(fresnel, fresnel, fresnel)
+            };
+            return Principled::reflect(&ggx, &wo, &h, hit_record, tint);
+        }
+        choice -= lobes.metallic + lobes.specular;
+
+        if choice < lobes.transmission {
+            return self.transmit(&parameters, &wo, hit_record, rng);
+        }
+
+        // Diffuse and sheen, cosine sampled.
+        let mut wi = *normal + Vec3::uniform_random_unit_vec(rng);
+        if wi.is_near_zero() {
+            wi = *normal;
+        }
+        self.evaluated(&parameters, &lobes, &wo, wi.unit_vec(), hit_record)
+    }
+
+    fn evaluate(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Option<Color> {
+        let wo = -ray.dir().unit_vec();
+        let parameters = self.parameters(hit_record);
+        let lobes = Principled::lobes(&parameters, wo.dot(hit_record.normal()));
+        let (value, _) = self.evaluate_lobes(&parameters, &lobes, &wo, &direction.unit_vec(), hit_record.normal())?;
+        Some(value)
+    }
+
+    fn pdf(&self, ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        let wo = -ray.dir().unit_vec();
+        let parameters = self.parameters(hit_record);
+        let lobes = Principled::lobes(&parameters, wo.dot(hit_record.normal()));
+        self.evaluate_lobes(&parameters, &lobes, &wo, &direction.unit_vec(), hit_record.normal()).map_or(0.0, |(_, pdf)| pdf)
+    }
+}
+
+// Normal-incidence reflectance of the specular coat over the diffuse base.
+fn specular_f0(parameters: &Parameters) -> f64 {
+    0.08 * parameters.specular
+}
+
+// Metals reflect their base color at normal incidence, going to white at grazing angles.
+fn metal_fresnel(parameters: &Parameters, cos: f64) -> Color {
+    let base_color = parameters.base_color;
+    Color::new(schlick(base_color.x(), cos), schlick(base_color.y(), cos), schlick(base_color.z(), cos))
+}
+
+// Burley's diffuse with retro-reflection at grazing angles, plus sheen, as a multiple of the
+// Lambertian BRDF 1/pi.
+fn diffuse_albedo(parameters: &Parameters, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> Color {
+    let cos_i = wi.dot(normal).max(0.0);
+    let cos_o = wo.dot(normal).max(0.0);
+    let h = *wo + *wi;
+    let cos_d = if h.is_near_zero() { 0.0 } else { wi.dot(&h.unit_vec()).max(0.0) };
+
+    let fd90 = 0.5 + 2.0 * parameters.roughness * cos_d * cos_d;
+    let retro = (1.0 + (fd90 - 1.0) * (1.0 - cos_i).powi(5)) * (1.0 + (fd90 - 1.0) * (1.0 - cos_o).powi(5));
+    let sheen = std::f64::consts::PI * parameters.sheen * (1.0 - cos_d).powi(5);
+    retro * parameters.base_color + Color::new(sheen, sheen, sheen)
+}
+
+fn constant(value: f64) -> Rc<dyn Texture> {
+    Rc::new(SolidColor::new(Color::new(value, value, value)))
+}
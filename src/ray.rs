@@ -1,8 +1,13 @@
+use crate::spectrum::SampledWavelengths;
 use crate::vec3::Vec3;
 
 pub struct Ray {
     orig: Vec3,
     dir: Vec3,
+
+    // Only set in spectral mode. Materials create scattered rays without it and the camera
+    // carries it over from the incoming ray.
+    wavelengths: Option<SampledWavelengths>,
 }
 
 impl Ray {
@@ -10,8 +15,14 @@ impl Ray {
         Self {
             orig: orig,
             dir: dir,
+            wavelengths: None,
         }
     }
+
+    pub fn with_wavelengths(mut self, wavelengths: Option<SampledWavelengths>) -> Self {
+        self.wavelengths = wavelengths;
+        self
+    }
     
     pub fn at(&self, t: f64) -> Vec3 {
         self.orig + (self.dir * t)
@@ -19,4 +30,8 @@ impl Ray {
 
     pub fn orig(&self) -> &Vec3 { &self.orig }
     pub fn dir(&self) -> &Vec3 { &self.dir }
+    pub fn wavelengths(&self) -> Option<&SampledWavelengths> { self.wavelengths.as_ref() }
+
+    // The hero wavelength in nanometers, or None when rendering in RGB.
+    pub fn wavelength(&self) -> Option<f64> { self.wavelengths.map(|w| w.hero()) }
 }
@@ -0,0 +1,517 @@
+// Scenes that can be selected from the command line. Each scene builds its world and
+// picks the camera placement that frames it.
+
+use crate::alpha_mask::{AlphaMask, AlphaMode};
+use crate::color::Color;
+use crate::hittable_list::HittableList;
+use crate::ies::IesProfile;
+use crate::light::{DirectionalLight, LightList, PointLight, SphereLight, SpotLight};
+use crate::medium::Medium;
+use crate::material::{Coated, Dielectric, Lambertian, Material, Metal, Mix, OrenNayar, RefractiveIndex, Subsurface};
+use crate::normal_map::{BumpMap, NormalMap};
+use crate::principled::Principled;
+use crate::sphere::Sphere;
+use crate::texture::{Checker, SolidColor, Waves};
+use crate::thin_film::ThinFilm;
+use crate::util::random;
+use crate::vec3::Vec3;
+
+use clap::ValueEnum;
+
+use std::rc::Rc;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SceneName {
+    // The final render from the end of the book.
+    Cover,
+    // The same at night, with a hundred or so of the small spheres glowing.
+    GlowingCover,
+    // Three spheres on a plane, with the glass sphere tinted by Beer-Lambert absorption.
+    TintedGlass,
+    // Spheres of increasingly dispersive glass over a striped floor. Render with --spectral.
+    Dispersion,
+    // A grid of spheres sweeping the parameters of the principled material.
+    Principled,
+    // Mixed and coated materials: car paint, varnished wood, lacquer and a checkered mix.
+    Layered,
+    // Normal-mapped and bump-mapped spheres.
+    Bumpy,
+    // Soap bubbles of different thicknesses and metals with an oxide film.
+    Iridescent,
+    // Skin, wax, marble and milk rendered with random-walk subsurface scattering.
+    Subsurface,
+    // Clay-like spheres going from Lambertian to very rough Oren-Nayar.
+    RoughDiffuse,
+    // Spheres with holes cut out by opacity textures.
+    Cutout,
+    // A night scene lit by a spot light, a point light and low moonlight. An --ies profile
+    // shapes the spot and point lights.
+    Lights,
+}
+
+// What rays that leave the scene see.
+pub enum Background {
+    // The blue-white gradient from the book.
+    Sky,
+    Solid(Color),
+}
+
+pub struct Scene {
+    world: HittableList,
+    lights: LightList,
+    background: Background,
+
+    vertical_fov_degrees: f64,
+    look_from: Vec3,
+    look_at: Vec3,
+    view_up: Vec3,
+    defocus_angle_degrees: f64,
+    focus_distance: f64,
+}
+
+impl Scene {
+    // `ies_profile` is applied to the point and spot lights of scenes that have them.
+    pub fn new(name: SceneName, ies_profile: Option<Rc<IesProfile>>, rng: &mut dyn rand::RngCore) -> Self {
+        match name {
+            SceneName::Cover => cover(rng, false),
+            SceneName::GlowingCover => cover(rng, true),
+            SceneName::TintedGlass => tinted_glass(),
+            SceneName::Dispersion => dispersion(),
+            SceneName::Principled => principled(),
+            SceneName::Layered => layered(),
+            SceneName::Bumpy => bumpy(),
+            SceneName::Iridescent => iridescent(),
+            SceneName::Subsurface => subsurface(),
+            SceneName::RoughDiffuse => rough_diffuse(),
+            SceneName::Cutout => cutout(),
+            SceneName::Lights => lights(ies_profile),
+        }
+    }
+
+    pub fn world(&self) -> &HittableList { &self.world }
+    pub fn lights(&self) -> &LightList { &self.lights }
+    pub fn vertical_fov_degrees(&self) -> f64 { self.vertical_fov_degrees }
+    pub fn look_from(&self) -> &Vec3 { &self.look_from }
+    pub fn look_at(&self) -> &Vec3 { &self.look_at }
+    pub fn view_up(&self) -> &Vec3 { &self.view_up }
+    pub fn defocus_angle_degrees(&self) -> f64 { self.defocus_angle_degrees }
+    pub fn focus_distance(&self) -> f64 { self.focus_distance }
+
+    // Light arriving from the background along `direction`.
+    pub fn background(&self, direction: &Vec3) -> Color {
+        match self.background {
+            Background::Sky => {
+                let unit_direction = direction.unit_vec();
+                let a = 0.5 * (unit_direction.y() + 1.0);  // interpolation variable
+                (1.0-a)*Color::new(1.0, 1.0, 1.0) + a*Color::new(0.5, 0.7, 1.0)
+            },
+            Background::Solid(color) => color,
+        }
+    }
+
+    // The background averaged over all directions.
+    pub fn average_background(&self) -> Color {
+        match self.background {
+            // The gradient is linear in the height of the direction, which is uniform over
+            // [-1, 1] for directions picked uniformly, so it averages to its midpoint.
+            Background::Sky => 0.5 * Color::new(1.0, 1.0, 1.0) + 0.5 * Color::new(0.5, 0.7, 1.0),
+            Background::Solid(color) => color,
+        }
+    }
+}
+
+// With `glowing`, some of the small spheres are lights and the sky is dark.
+fn cover(rng: &mut dyn rand::RngCore, glowing: bool) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = LightList::new();
+
+    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0,-1000.0,0.0), 1000.0, ground_material)));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Vec3::new((a as f64) + 0.9 * random(0.0, 1.0, rng), 0.2, (b as f64) + 0.9 * random(0.0, 1.0, rng));
+            if (center - Vec3::new(4.0, 0.2, 0.0)).len() > 0.9 {
+                let choose_material = random(0.0, 1.0, rng);
+                match choose_material {
+                    0.0..0.3 if glowing => {
+                        // Mostly dim lights with a few bright ones.
+                        let emission = Color::random_vec(0.3, 1.0, rng) * (20.0 * random(0.0, 1.0, rng).powi(4));
+                        let light = Rc::new(SphereLight::new(center, 0.2, emission));
+                        world.add(light.clone());
+                        lights.add(light);
+                    },
+                    0.0..0.8 => {
+                        let albedo = Color::random_vec(0.0, 1.0, rng) * Color::random_vec(0.0, 1.0, rng);
+                        let sphere_material = Rc::new(Lambertian::new(albedo));
+                        world.add(Rc::new(Sphere::new(center, 0.2, sphere_material)));
+                    },
+                    0.8..0.95 => {
+                        let albedo = Color::random_vec(0.5, 1.0, rng);
+                        let fuzz = random(0.0, 0.5, rng);
+                        let sphere_material = Rc::new(Metal::new(albedo, fuzz));
+                        world.add(Rc::new(Sphere::new(center, 0.2, sphere_material)));
+                    },
+                    _ => {
+                        let sphere_material = Rc::new(Dielectric::new(1.5));
+                        world.add(Rc::new(Sphere::new(center, 0.2, sphere_material)));
+                    }
+                }
+            }
+        }
+    }
+
+    let material1 = Rc::new(Dielectric::new(1.5));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, material1)));
+
+    let material2 = Rc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    world.add(Rc::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, material2)));
+
+    let material3 = Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    world.add(Rc::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, material3)));
+
+    Scene {
+        world: world,
+        lights: lights,
+        background: if glowing { Background::Solid(Color::new(0.0, 0.0, 0.0)) } else { Background::Sky },
+        vertical_fov_degrees: 20.0,
+        look_from: Vec3::new(13.0,2.0,3.0),
+        look_at: Vec3::new(0.0,0.0,0.0),
+        view_up: Vec3::new(0.0,1.0,0.0),
+        defocus_angle_degrees: 0.6,
+        focus_distance: 10.0,
+    }
+}
+
+fn tinted_glass() -> Scene {
+    let mut world = HittableList::new();
+
+    let material_ground = Rc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
+    let material_center = Rc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5)));
+    // Light crossing the full diameter of the sphere comes out green.
+    let material_left = Rc::new(Dielectric::new(1.5).with_transmission_color(Color::new(0.2, 0.8, 0.3), 1.0));
+    let material_right = Rc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.0));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0, material_ground)));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, 0.0, -1.2), 0.5, material_center)));
+    world.add(Rc::new(Sphere::new(Vec3::new(-1.0, 0.0, -1.0), 0.5, material_left)));
+    world.add(Rc::new(Sphere::new(Vec3::new(1.0, 0.0, -1.0), 0.5, material_right)));
+
+    Scene {
+        world: world,
+        lights: LightList::new(),
+        background: Background::Sky,
+        vertical_fov_degrees: 20.0,
+        look_from: Vec3::new(-2.0, 2.0, 1.0),
+        look_at: Vec3::new(0.0, 0.0, -1.0),
+        view_up: Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle_degrees: 0.0,
+        focus_distance: 3.4,
+    }
+}
+
+fn dispersion() -> Scene {
+    let mut world = HittableList::new();
+
+    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_material)));
+
+    // Rows of small dark and light spheres behind the glass give sharp edges for the
+    // dispersion to split into colors.
+    let dark = Rc::new(Lambertian::new(Color::new(0.05, 0.05, 0.05)));
+    let light = Rc::new(Lambertian::new(Color::new(0.9, 0.9, 0.9)));
+    for i in -12..=12 {
+        let material = if i % 2 == 0 { dark.clone() } else { light.clone() };
+        world.add(Rc::new(Sphere::new(Vec3::new(-3.0, 0.25, 0.5 * (i as f64)), 0.25, material)));
+    }
+
+    let curves = [
+        RefractiveIndex::Cauchy { a: 1.5046, b: 0.0042 },
+        RefractiveIndex::bk7(),
+        RefractiveIndex::dense_flint(),
+        RefractiveIndex::diamond(),
+    ];
+    for (i, curve) in curves.into_iter().enumerate() {
+        let z = 2.4 * (i as f64) - 3.6;
+        world.add(Rc::new(Sphere::new(Vec3::new(0.0, 1.0, z), 1.0, Rc::new(Dielectric::dispersive(curve)))));
+    }
+
+    Scene {
+        world: world,
+        lights: LightList::new(),
+        background: Background::Sky,
+        vertical_fov_degrees: 40.0,
+        look_from: Vec3::new(10.0, 3.0, 0.0),
+        look_at: Vec3::new(0.0, 0.8, 0.0),
+        view_up: Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle_degrees: 0.0,
+        focus_distance: 10.0,
+    }
+}
+
+fn principled() -> Scene {
+    let mut world = HittableList::new();
+
+    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_material)));
+
+    // Each row varies one parameter from 0 to 1, left to right.
+    let base_color = Color::new(0.8, 0.3, 0.2);
+    let rows: [fn(Principled, f64) -> Principled; 5] = [
+        |m, t| m.with_metallic(1.0).with_roughness(t),
+        |m, t| m.with_roughness(t),
+        |m, t| m.with_roughness(0.6).with_clearcoat(t, 0.05),
+        |m, t| m.with_roughness(1.0).with_sheen(t),
+        |m, t| m.with_roughness(0.1).with_specular(1.0).with_transmission(t, 1.5),
+    ];
+    for (row, configure) in rows.iter().enumerate() {
+        for col in 0..5 {
+            let t = (col as f64) / 4.0;
+            let material = Rc::new(configure(Principled::new(base_color), t));
+            let center = Vec3::new(2.5 * (col as f64) - 5.0, 1.0, 2.5 * (row as f64) - 5.0);
+            world.add(Rc::new(Sphere::new(center, 1.0, material)));
+        }
+    }
+
+    Scene {
+        world: world,
+        lights: LightList::new(),
+        background: Background::Sky,
+        vertical_fov_degrees: 35.0,
+        look_from: Vec3::new(0.0, 18.0, 14.0),
+        look_at: Vec3::new(0.0, 0.0, 0.0),
+        view_up: Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle_degrees: 0.0,
+        focus_distance: 10.0,
+    }
+}
+
+fn layered() -> Scene {
+    let mut world = HittableList::new();
+
+    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_material)));
+
+    let flakes = Rc::new(Principled::new(Color::new(0.6, 0.05, 0.05)).with_metallic(1.0).with_roughness(0.5));
+    let car_paint = Rc::new(Coated::new(flakes, 1.5));
+
+    let wood = Rc::new(Lambertian::new(Color::new(0.4, 0.2, 0.08)));
+    let varnished_wood = Rc::new(Coated::new(wood, 1.5).with_roughness(0.15).with_tint(Color::new(0.95, 0.8, 0.5)));
+
+    let lacquer = Rc::new(Coated::new(Rc::new(Lambertian::new(Color::new(0.05, 0.05, 0.05))), 1.55));
+
+    let matte = Rc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+    let gold = Rc::new(Metal::new(Color::new(0.9, 0.7, 0.3), 0.1));
+    let weight = Rc::new(Checker::new(8, 4, Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0)));
+    let checkered = Rc::new(Mix::textured(matte.clone(), gold.clone(), weight));
+    let half_and_half = Rc::new(Mix::new(matte, gold, 0.5));
+
+    let materials: [Rc<dyn Material>; 5] = [car_paint, varnished_wood, lacquer, checkered, half_and_half];
+    for (i, material) in materials.into_iter().enumerate() {
+        world.add(Rc::new(Sphere::new(Vec3::new(2.2 * (i as f64) - 4.4, 1.0, 0.0), 1.0, material)));
+    }
+
+    Scene {
+        world: world,
+        lights: LightList::new(),
+        background: Background::Sky,
+        vertical_fov_degrees: 30.0,
+        look_from: Vec3::new(0.0, 4.0, 16.0),
+        look_at: Vec3::new(0.0, 0.8, 0.0),
+        view_up: Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle_degrees: 0.0,
+        focus_distance: 10.0,
+    }
+}
+
+fn bumpy() -> Scene {
+    let mut world = HittableList::new();
+
+    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_material)));
+
+    // Facets that tilt alternately left and right along u.
+    let facets = Rc::new(Checker::new(24, 12, Color::new(0.8, 0.5, 0.9), Color::new(0.2, 0.5, 0.9)));
+    let faceted_metal = Rc::new(NormalMap::new(Rc::new(Metal::new(Color::new(0.8, 0.8, 0.85), 0.0)), facets, 1.0));
+    world.add(Rc::new(Sphere::new(Vec3::new(-2.2, 1.0, 0.0), 1.0, faceted_metal)));
+
+    let dimples = Rc::new(Waves::new(32, 16));
+    let golf_ball = Rc::new(BumpMap::new(Rc::new(Lambertian::new(Color::new(0.9, 0.9, 0.9))), dimples.clone(), -0.01));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, golf_ball)));
+
+    let hammered = Rc::new(BumpMap::new(Rc::new(Coated::new(Rc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5))), 1.5)), dimples, 0.02));
+    world.add(Rc::new(Sphere::new(Vec3::new(2.2, 1.0, 0.0), 1.0, hammered)));
+
+    Scene {
+        world: world,
+        lights: LightList::new(),
+        background: Background::Sky,
+        vertical_fov_degrees: 30.0,
+        look_from: Vec3::new(0.0, 3.0, 12.0),
+        look_at: Vec3::new(0.0, 0.9, 0.0),
+        view_up: Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle_degrees: 0.0,
+        focus_distance: 10.0,
+    }
+}
+
+fn iridescent() -> Scene {
+    let mut world = HittableList::new();
+
+    let ground_material = Rc::new(Lambertian::new(Color::new(0.2, 0.2, 0.2)));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_material)));
+
+    // A bubble is a film of soapy water with air on both sides, so the "glass" is just air.
+    for (i, thickness_nm) in [250.0, 400.0, 550.0].into_iter().enumerate() {
+        let bubble = Rc::new(Dielectric::new(1.0).with_thin_film(ThinFilm::new(thickness_nm, 1.33)));
+        world.add(Rc::new(Sphere::new(Vec3::new(2.2 * (i as f64) - 2.2, 2.6, 0.0), 1.0, bubble)));
+    }
+
+    // Anodized titanium and tempered steel get their colors from an oxide layer.
+    let titanium = Rc::new(Metal::new(Color::new(0.55, 0.5, 0.45), 0.05).with_thin_film(ThinFilm::new(120.0, 2.4)));
+    world.add(Rc::new(Sphere::new(Vec3::new(-1.1, 0.8, 0.0), 0.8, titanium)));
+    let steel = Rc::new(Metal::new(Color::new(0.6, 0.6, 0.6), 0.1).with_thin_film(ThinFilm::new(60.0, 2.2)));
+    world.add(Rc::new(Sphere::new(Vec3::new(1.1, 0.8, 0.0), 0.8, steel)));
+
+    Scene {
+        world: world,
+        lights: LightList::new(),
+        background: Background::Sky,
+        vertical_fov_degrees: 30.0,
+        look_from: Vec3::new(0.0, 3.0, 14.0),
+        look_at: Vec3::new(0.0, 1.6, 0.0),
+        view_up: Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle_degrees: 0.0,
+        focus_distance: 10.0,
+    }
+}
+
+fn subsurface() -> Scene {
+    let mut world = HittableList::new();
+
+    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_material)));
+
+    // Red light travels furthest in skin, which gives it its warm glow.
+    let skin = Medium::from_albedo(Color::new(0.99, 0.93, 0.88), Color::new(0.3, 0.15, 0.1), 0.8);
+    let wax = Medium::from_albedo(Color::new(0.98, 0.9, 0.7), Color::new(0.2, 0.2, 0.2), 0.0);
+    let marble = Medium::from_albedo(Color::new(0.99, 0.99, 0.97), Color::new(0.1, 0.1, 0.1), 0.0);
+    let milk = Medium::from_albedo(Color::new(0.999, 0.998, 0.99), Color::new(0.06, 0.07, 0.09), 0.7);
+    let media = [(1.4, skin), (1.45, wax), (1.5, marble), (1.35, milk)];
+    for (i, (refractive_index, medium)) in media.into_iter().enumerate() {
+        let material = Rc::new(Subsurface::new(refractive_index, medium));
+        world.add(Rc::new(Sphere::new(Vec3::new(2.2 * (i as f64) - 3.3, 1.0, 0.0), 1.0, material)));
+    }
+
+    Scene {
+        world: world,
+        lights: LightList::new(),
+        background: Background::Sky,
+        vertical_fov_degrees: 30.0,
+        look_from: Vec3::new(0.0, 3.0, 14.0),
+        look_at: Vec3::new(0.0, 0.9, 0.0),
+        view_up: Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle_degrees: 0.0,
+        focus_distance: 10.0,
+    }
+}
+
+fn rough_diffuse() -> Scene {
+    let mut world = HittableList::new();
+
+    let ground_material = Rc::new(OrenNayar::new(Color::new(0.5, 0.5, 0.5), 30.0));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_material)));
+
+    let clay = Color::new(0.7, 0.45, 0.3);
+    for (i, sigma_degrees) in [0.0, 10.0, 20.0, 40.0, 60.0].into_iter().enumerate() {
+        let material = Rc::new(OrenNayar::new(clay, sigma_degrees));
+        world.add(Rc::new(Sphere::new(Vec3::new(2.2 * (i as f64) - 4.4, 1.0, 0.0), 1.0, material)));
+    }
+
+    Scene {
+        world: world,
+        lights: LightList::new(),
+        background: Background::Sky,
+        vertical_fov_degrees: 30.0,
+        look_from: Vec3::new(0.0, 4.0, 16.0),
+        look_at: Vec3::new(0.0, 0.8, 0.0),
+        view_up: Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle_degrees: 0.0,
+        focus_distance: 10.0,
+    }
+}
+
+fn cutout() -> Scene {
+    let mut world = HittableList::new();
+
+    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_material)));
+
+    let opaque = Color::new(1.0, 1.0, 1.0);
+    let clear = Color::new(0.0, 0.0, 0.0);
+
+    // A checkered cage with a smaller sphere inside, visible through the holes.
+    let cage_material = Rc::new(Lambertian::new(Color::new(0.8, 0.3, 0.1)));
+    let cage = Rc::new(Sphere::new(Vec3::new(-1.2, 1.0, 0.0), 1.0, cage_material));
+    let holes = Rc::new(Checker::new(16, 8, opaque, clear));
+    world.add(Rc::new(AlphaMask::new(cage, holes, AlphaMode::Threshold(0.5))));
+    let core_material = Rc::new(Metal::new(Color::new(0.8, 0.8, 0.9), 0.0));
+    world.add(Rc::new(Sphere::new(Vec3::new(-1.2, 1.0, 0.0), 0.5, core_material)));
+
+    // A sphere that is 30% opaque everywhere, like tinted net curtain.
+    let veil_material = Rc::new(Lambertian::new(Color::new(0.1, 0.3, 0.8)));
+    let veil = Rc::new(Sphere::new(Vec3::new(1.2, 1.0, 0.0), 1.0, veil_material));
+    let coverage = Rc::new(SolidColor::new(Color::new(0.3, 0.3, 0.3)));
+    world.add(Rc::new(AlphaMask::new(veil, coverage, AlphaMode::Stochastic)));
+
+    Scene {
+        world: world,
+        lights: LightList::new(),
+        background: Background::Sky,
+        vertical_fov_degrees: 30.0,
+        look_from: Vec3::new(0.0, 3.0, 10.0),
+        look_at: Vec3::new(0.0, 0.9, 0.0),
+        view_up: Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle_degrees: 0.0,
+        focus_distance: 10.0,
+    }
+}
+
+fn lights(ies_profile: Option<Rc<IesProfile>>) -> Scene {
+    let mut world = HittableList::new();
+
+    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Rc::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_material)));
+
+    let clay = Rc::new(OrenNayar::new(Color::new(0.7, 0.45, 0.3), 30.0));
+    let plastic = Rc::new(Principled::new(Color::new(0.2, 0.4, 0.8)).with_roughness(0.3).with_clearcoat(0.5, 0.2));
+    let plaster = Rc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+    let lacquer = Rc::new(Coated::new(Rc::new(Lambertian::new(Color::new(0.6, 0.1, 0.1))), 1.5).with_roughness(0.2));
+    let brushed_metal = Rc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.3));
+    let materials: [Rc<dyn Material>; 5] = [clay, plastic, plaster, lacquer, brushed_metal];
+    for (i, material) in materials.into_iter().enumerate() {
+        world.add(Rc::new(Sphere::new(Vec3::new(2.2 * (i as f64) - 4.4, 1.0, 0.0), 1.0, material)));
+    }
+
+    let mut spot = SpotLight::new(Vec3::new(0.0, 6.0, 2.0), Vec3::new(0.0, 0.0, 0.0), Color::new(40.0, 38.0, 34.0), 15.0, 25.0);
+    let mut point = PointLight::new(Vec3::new(-4.0, 2.5, 3.0), Color::new(6.0, 3.0, 1.0));
+    if let Some(profile) = ies_profile {
+        spot = spot.with_profile(profile.clone());
+        point = point.with_profile(profile);
+    }
+    let mut lights = LightList::new();
+    lights.add(Rc::new(spot));
+    lights.add(Rc::new(point));
+    lights.add(Rc::new(DirectionalLight::new(Vec3::new(1.0, -0.5, -1.0), Color::new(0.1, 0.12, 0.2))));
+
+    Scene {
+        world: world,
+        lights: lights,
+        background: Background::Solid(Color::new(0.01, 0.01, 0.02)),
+        vertical_fov_degrees: 30.0,
+        look_from: Vec3::new(0.0, 4.0, 16.0),
+        look_at: Vec3::new(0.0, 0.9, 0.0),
+        view_up: Vec3::new(0.0, 1.0, 0.0),
+        defocus_angle_degrees: 0.0,
+        focus_distance: 10.0,
+    }
+}
@@ -0,0 +1,125 @@
+// Support for spectral rendering with hero wavelength sampling (Wilkie et al. 2014).
+//
+// Each camera path carries a small set of wavelengths: a randomly sampled "hero" plus
+// others spaced evenly across the visible range. Radiance along the path is stored in a
+// Vec3 whose components are the values at those wavelengths, which lets the path tracer
+// use the same code in RGB and spectral mode.
+
+use crate::color::Color;
+use crate::util::random;
+use crate::vec3::Vec3;
+
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 780.0;
+
+// One wavelength per component of Vec3.
+const N_WAVELENGTHS: usize = 3;
+
+// Integrals of the color matching functions below over [LAMBDA_MIN, LAMBDA_MAX]. Dividing
+// by these maps a constant spectrum of 1 to XYZ = (1, 1, 1).
+const CIE_X_INTEGRAL: f64 = 106.765056;
+const CIE_Y_INTEGRAL: f64 = 106.919747;
+const CIE_Z_INTEGRAL: f64 = 106.825662;
+
+// XYZ of the D65 white point, used to white-balance the equal-energy spectrum to D65.
+const D65_WHITE_X: f64 = 0.95047;
+const D65_WHITE_Z: f64 = 1.08883;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SampledWavelengths {
+    // Wavelengths in nanometers. lambda[0] is the hero wavelength.
+    lambda: [f64; N_WAVELENGTHS],
+
+    // Set once the path has hit something whose behavior depends on the wavelength (e.g.
+    // a dispersive dielectric). From then on only the hero wavelength is carried.
+    secondary_terminated: bool,
+}
+
+impl SampledWavelengths {
+    // Samples the hero uniformly over the visible range and places the others at equal
+    // offsets, wrapping around. Every wavelength is then uniformly distributed.
+    pub fn sample(rng: &mut dyn rand::RngCore) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = random(LAMBDA_MIN, LAMBDA_MAX, rng);
+        let mut lambda = [hero; N_WAVELENGTHS];
+        for (i, l) in lambda.iter_mut().enumerate().skip(1) {
+            let shifted = hero + (i as f64) * range / (N_WAVELENGTHS as f64);
+            *l = if shifted >= LAMBDA_MAX { shifted - range } else { shifted };
+        }
+        Self {
+            lambda: lambda,
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 { self.lambda[0] }
+    pub fn lambdas(&self) -> [f64; N_WAVELENGTHS] { self.lambda }
+    pub fn secondary_terminated(&self) -> bool { self.secondary_terminated }
+
+    pub fn terminate_secondary(&self) -> Self {
+        Self {
+            lambda: self.lambda,
+            secondary_terminated: true,
+        }
+    }
+
+    // Weights to fold into the path throughput when secondaries are terminated. The hero
+    // now has to stand in for all the wavelengths, so it is scaled up accordingly.
+    pub fn termination_weights() -> Vec3 {
+        Vec3::new(N_WAVELENGTHS as f64, 0.0, 0.0)
+    }
+
+    // Evaluates an RGB reflectance or radiance at the sampled wavelengths.
+    //
+    // The spectrum is r * R(l) + g * G(l) + b * B(l) with smooth basis functions that sum
+    // to 1 everywhere, so white maps to a flat spectrum and reflectances in [0, 1] stay in
+    // [0, 1]. The round trip back to RGB is close but not exact for saturated colors.
+    pub fn lift_rgb(&self, color: &Color) -> Vec3 {
+        let value = |l: f64| {
+            let b = 1.0 / (1.0 + ((l - 490.0) / 12.0).exp());
+            let r = 1.0 / (1.0 + (-(l - 585.0) / 12.0).exp());
+            let g = 1.0 - r - b;
+            color.x() * r + color.y() * g + color.z() * b
+        };
+        Vec3::new(value(self.lambda[0]), value(self.lambda[1]), value(self.lambda[2]))
+    }
+
+    // Monte Carlo estimate of the XYZ tristimulus values of a spectrum known only at the
+    // sampled wavelengths. The result is white-balanced so that a flat spectrum maps to
+    // the D65 white point.
+    pub fn estimate_xyz(&self, values: &Vec3) -> Vec3 {
+        let values = [values.x(), values.y(), values.z()];
+        let pdf = 1.0 / (LAMBDA_MAX - LAMBDA_MIN);
+        let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+        for (l, v) in self.lambda.iter().zip(values) {
+            xyz += v * cie_xyz(*l) / pdf;
+        }
+        xyz /= N_WAVELENGTHS as f64;
+        Vec3::new(
+            xyz.x() * D65_WHITE_X / CIE_X_INTEGRAL,
+            xyz.y() / CIE_Y_INTEGRAL,
+            xyz.z() * D65_WHITE_Z / CIE_Z_INTEGRAL,
+        )
+    }
+}
+
+// Piecewise Gaussian used by the fit below.
+fn piecewise_gaussian(lambda: f64, mu: f64, sigma_below: f64, sigma_above: f64) -> f64 {
+    let sigma = if lambda < mu { sigma_below } else { sigma_above };
+    let t = (lambda - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 2-degree color matching functions, using the multi-lobe analytic fit from
+// Wyman, Sloan and Shirley, "Simple Analytic Approximations to the CIE XYZ Color
+// Matching Functions" (2013).
+fn cie_xyz(lambda: f64) -> Vec3 {
+    let x = 1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8);
+    Vec3::new(x, y, z)
+}
@@ -20,6 +20,28 @@ impl Sphere {
             material: material,
         }
     }
+
+    // Maps a point on the unit sphere to (u, v): u is the angle around the Y axis starting
+    // from -X, and v is the angle from -Y up to +Y, both scaled to [0, 1].
+    fn uv(point: &Vec3) -> (f64, f64) {
+        let theta = (-point.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-point.z()).atan2(point.x()) + std::f64::consts::PI;
+        (phi / (2.0 * std::f64::consts::PI), theta / std::f64::consts::PI)
+    }
+
+    // Derivatives of the surface point with respect to the (u, v) from `uv`, given the
+    // point on the unit sphere. dp/dv is undefined at the poles, where it's left as zero.
+    fn tangents(&self, point: &Vec3) -> (Vec3, Vec3) {
+        let pi = std::f64::consts::PI;
+        let dpdu = 2.0 * pi * self.radius * Vec3::new(point.z(), 0.0, -point.x());
+        let sin_theta = (1.0 - point.y() * point.y()).max(0.0).sqrt();
+        let dpdv = if sin_theta > 1e-8 {
+            pi * self.radius * Vec3::new(-point.x() * point.y() / sin_theta, sin_theta, -point.z() * point.y() / sin_theta)
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        };
+        (dpdu, dpdv)
+    }
 }
 
 impl Hit for Sphere {
@@ -45,6 +67,9 @@ impl Hit for Sphere {
         let point =  ray.at(root);
         let outward_normal = (point - self.center) / self.radius;
 
-        Some(HitRecord::new(&point, ray, root, &outward_normal, self.material.clone()))
+        let (u, v) = Sphere::uv(&outward_normal);
+        let (dpdu, dpdv) = self.tangents(&outward_normal);
+
+        Some(HitRecord::new(&point, ray, root, &outward_normal, self.material.clone()).with_uv(u, v).with_tangents(dpdu, dpdv))
     }
 }
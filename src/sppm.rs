@@ -0,0 +1,338 @@
+// Stochastic progressive photon mapping (Hachisuka and Jensen 2009), after the structure of
+// pbrt. Each iteration follows one camera path per pixel through mirrors and glass to the
+// first surface whose material can be evaluated, the pixel's visible point. Then photons are
+// shot from the lights, and those landing near a visible point add to its pixel. Each pixel's
+// search radius shrinks as photons arrive, so the estimate converges, and caustics cast on
+// diffuse surfaces, which camera paths only find by chance, come out clean.
+//
+// Photons come from the scene's lights, emissive objects among them, and from the
+// background. Visible points sample the lights directly, and follow one scattered ray to
+// find the background, so photons are only gathered after their first bounce. Background
+// photons are shot from a disk facing the direction they come from, just outside a sphere
+// bounding the iteration's visible points. That covers the light that gets to them, except
+// background light that first bounces off geometry outside the disk's reach. Directional
+// lights don't shoot photons, so they only light surfaces directly, and neither do emissive
+// objects that aren't registered as lights. Participating media are ignored, and rendering
+// is in RGB.
+
+use crate::camera::{Camera, MIN_T_TO_PREVENT_SHADOW_ACNE};
+use crate::color::Color;
+use crate::film::Film;
+use crate::hit::{Hit, HitRecord};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::util::random;
+use crate::vec3::Vec3;
+
+use std::collections::HashMap;
+
+// Fraction of the photons found in an iteration that count towards a pixel's total, which
+// sets how quickly its radius shrinks. The paper suggests 2/3.
+const ALPHA: f64 = 2.0 / 3.0;
+
+struct VisiblePoint {
+    hit_record: HitRecord,
+    incoming: Ray,
+    beta: Color,  // Throughput of the camera path up to the point
+}
+
+struct Pixel {
+    radius: f64,
+    direct: Color,  // Sum over iterations of the light the camera paths found themselves
+    visible_point: Option<VisiblePoint>,
+    photon_count: f64,  // Photons credited to the pixel so far, N in the paper
+    flux: Color,  // Flux carried by those photons, scaled to the current radius (tau)
+    new_photon_count: u32,  // Photons found in this iteration, M in the paper
+    new_flux: Color,  // Flux they carry towards the camera (Phi)
+}
+
+impl Pixel {
+    fn new(radius: f64) -> Self {
+        Self {
+            radius: radius,
+            direct: Color::new(0.0, 0.0, 0.0),
+            visible_point: None,
+            photon_count: 0.0,
+            flux: Color::new(0.0, 0.0, 0.0),
+            new_photon_count: 0,
+            new_flux: Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Gathers a photon with throughput `beta`, travelling along `direction`, that landed at
+    // `point`.
+    fn add_photon(&mut self, point: &Vec3, direction: &Vec3, beta: &Color) {
+        let Some(visible_point) = &self.visible_point else { return };
+        let hit_record = &visible_point.hit_record;
+        if (*point - *hit_record.point()).len_sq() > self.radius * self.radius {
+            return;
+        }
+        self.new_photon_count += 1;
+
+        // Photon density per unit area already accounts for the cosine at the surface, so
+        // it is divided back out of the evaluated BSDF.
+        let towards_light = -direction.unit_vec();
+        let cos = towards_light.dot(hit_record.normal()).abs();
+        if cos <= 0.0 {
+            return;
+        }
+        let Some(bsdf) = hit_record.material().evaluate(&visible_point.incoming, hit_record, &towards_light) else { return };
+        self.new_flux += visible_point.beta * bsdf * *beta / cos;
+    }
+
+    // Folds the photons found in this iteration into the totals and shrinks the radius.
+    fn update(&mut self) {
+        if self.new_photon_count > 0 {
+            let new_photon_count = self.new_photon_count as f64;
+            let photon_count = self.photon_count + ALPHA * new_photon_count;
+            let radius = self.radius * (photon_count / (self.photon_count + new_photon_count)).sqrt();
+            self.flux = (self.flux + self.new_flux) * ((radius * radius) / (self.radius * self.radius));
+            self.photon_count = photon_count;
+            self.radius = radius;
+        }
+        self.new_photon_count = 0;
+        self.new_flux = Color::new(0.0, 0.0, 0.0);
+        self.visible_point = None;
+    }
+}
+
+// Pixels bucketed by the cells of a uniform grid that their visible point's search sphere
+// overlaps. Only cells holding some pixel are stored.
+struct Grid {
+    cell_size: f64,
+    cells: HashMap<(i64, i64, i64), Vec<usize>>,
+}
+
+impl Grid {
+    fn new(pixels: &[Pixel]) -> Self {
+        let with_visible_point = || pixels.iter().enumerate().filter(|(_, pixel)| pixel.visible_point.is_some());
+        // With cells as large as the largest radius, each sphere spans at most 3 cells along
+        // each axis, so at most 27 in all.
+        let cell_size = with_visible_point().fold(0.0_f64, |size, (_, pixel)| size.max(pixel.radius));
+        let mut grid = Self {
+            cell_size: cell_size,
+            cells: HashMap::new(),
+        };
+        if cell_size <= 0.0 {
+            return grid;
+        }
+
+        for (index, pixel) in with_visible_point() {
+            let Some(visible_point) = &pixel.visible_point else { continue };
+            let offset = Vec3::new(pixel.radius, pixel.radius, pixel.radius);
+            let low = grid.cell(&(*visible_point.hit_record.point() - offset));
+            let high = grid.cell(&(*visible_point.hit_record.point() + offset));
+            for x in low.0..=high.0 {
+                for y in low.1..=high.1 {
+                    for z in low.2..=high.2 {
+                        grid.cells.entry((x, y, z)).or_default().push(index);
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    fn cell(&self, point: &Vec3) -> (i64, i64, i64) {
+        let coordinate = |value: f64| (value / self.cell_size).floor() as i64;
+        (coordinate(point.x()), coordinate(point.y()), coordinate(point.z()))
+    }
+
+    // Pixels whose visible point may be within their radius of `point`.
+    fn pixels_near(&self, point: &Vec3) -> &[usize] {
+        if self.cell_size <= 0.0 {
+            return &[];
+        }
+        self.cells.get(&self.cell(point)).map_or(&[], |pixels| pixels.as_slice())
+    }
+}
+
+// Renders the image with one camera path per pixel and `camera.photons_per_iteration()`
+// photons in each of `camera.samples_per_pixel()` iterations.
+pub fn render(camera: &Camera, scene: &Scene, rng: &mut dyn rand::RngCore) -> Film {
+    let width = camera.image_width();
+    let height = camera.image_height();
+    let iterations = camera.samples_per_pixel().max(1);
+    let mut pixels: Vec<Pixel> = (0..width * height).map(|_| Pixel::new(camera.photon_radius())).collect();
+
+    for iteration in 0..iterations {
+        let iterations_remaining = iterations - iteration;
+        if iterations_remaining.is_multiple_of(10) {
+            eprintln!("Iterations remaining: {}", iterations_remaining);
+        }
+
+        for row in 0..height {
+            for col in 0..width {
+                let ray = camera.get_ray(row, col, rng);
+                find_visible_point(camera, scene, &ray, &mut pixels[(row * width + col) as usize], rng);
+            }
+        }
+
+        let grid = Grid::new(&pixels);
+        let sources = Sources::new(scene, visible_bounds(&pixels));
+        for _ in 0..camera.photons_per_iteration() {
+            trace_photon(camera, scene, &grid, &sources, &mut pixels, rng);
+        }
+        for pixel in pixels.iter_mut() {
+            pixel.update();
+        }
+    }
+
+    // Every photon shot could have landed in any pixel, so the flux is shared out over all
+    // of them.
+    let photons = iterations as f64 * camera.photons_per_iteration() as f64;
+    let mut film = Film::new(width, height, 1);
+    for row in 0..height {
+        for col in 0..width {
+            let pixel = &pixels[(row * width + col) as usize];
+            let mut color = pixel.direct / iterations as f64;
+            if photons > 0.0 {
+                color += pixel.flux / (photons * std::f64::consts::PI * pixel.radius * pixel.radius);
+            }
+            film.add_sample(col as f64 + 0.5, row as f64 + 0.5, &color, &[]);
+        }
+    }
+    film
+}
+
+// Follows `ray` through materials that can't be evaluated until it reaches one that can,
+// and leaves a visible point there. Light the path finds along the way, including direct
+// lighting at the visible point, goes straight into the pixel.
+fn find_visible_point(camera: &Camera, scene: &Scene, ray: &Ray, pixel: &mut Pixel, rng: &mut dyn rand::RngCore) {
+    let mut ray = Ray::new(*ray.orig(), *ray.dir());
+    let mut beta = Color::new(1.0, 1.0, 1.0);
+    for _ in 0..camera.max_depth() {
+        let Some(hit_record) = first_hit(scene.world(), &ray) else {
+            pixel.direct += beta * scene.background(ray.dir());
+            return;
+        };
+        let material = hit_record.material();
+        pixel.direct += beta * material.emitted(&ray, &hit_record);
+
+        // Evaluating returns None for materials that can't be evaluated in any direction.
+        if material.evaluate(&ray, &hit_record, hit_record.normal()).is_some() {
+            pixel.direct += beta * camera.direct_lighting(&ray, &hit_record, scene, rng);
+            // The background isn't one of the lights, so it is found by a scattered ray.
+            if let Some(result) = material.scatter(&ray, &hit_record, rng)
+                && first_hit(scene.world(), result.scattered()).is_none() {
+                pixel.direct += beta * *result.attenuation() * scene.background(result.scattered().dir());
+            }
+            pixel.visible_point = Some(VisiblePoint {
+                hit_record: hit_record,
+                incoming: ray,
+                beta: beta,
+            });
+            return;
+        }
+
+        let Some(result) = material.scatter(&ray, &hit_record, rng) else { return };
+        beta = beta * *result.attenuation();
+        ray = Ray::new(*result.scattered().orig(), *result.scattered().dir());
+    }
+}
+
+// Center and radius of a sphere holding the search spheres of all the visible points, or
+// None if there are none.
+fn visible_bounds(pixels: &[Pixel]) -> Option<(Vec3, f64)> {
+    let mut low = Vec3::new(f64::MAX, f64::MAX, f64::MAX);
+    let mut high = Vec3::new(f64::MIN, f64::MIN, f64::MIN);
+    let mut max_radius = 0.0_f64;
+    for pixel in pixels {
+        let Some(visible_point) = &pixel.visible_point else { continue };
+        let point = visible_point.hit_record.point();
+        low = Vec3::new(low.x().min(point.x()), low.y().min(point.y()), low.z().min(point.z()));
+        high = Vec3::new(high.x().max(point.x()), high.y().max(point.y()), high.z().max(point.z()));
+        max_radius = max_radius.max(pixel.radius);
+    }
+    if low.x() > high.x() {
+        return None;
+    }
+    Some((0.5 * (low + high), 0.5 * (high - low).len() + max_radius))
+}
+
+// Where the photons of one iteration come from: the scene's lights and the background, with
+// the power each gives off.
+struct Sources {
+    bounds: Option<(Vec3, f64)>,  // From `visible_bounds`
+    light_power: f64,
+    background_power: f64,  // Reaching the sphere in `bounds`
+}
+
+impl Sources {
+    fn new(scene: &Scene, bounds: Option<(Vec3, f64)>) -> Self {
+        let background_power = bounds.map_or(0.0, |(_, radius)| {
+            let background = scene.average_background();
+            let area = std::f64::consts::PI * radius * radius;
+            4.0 * std::f64::consts::PI * area * (background.x() + background.y() + background.z()) / 3.0
+        });
+        Self {
+            bounds: bounds,
+            light_power: scene.lights().power(),
+            background_power: background_power,
+        }
+    }
+}
+
+// Shoots a photon from one of `sources`, picked in proportion to their power, and hands it
+// to every visible point near where it lands.
+fn trace_photon(camera: &Camera, scene: &Scene, grid: &Grid, sources: &Sources, pixels: &mut [Pixel], rng: &mut dyn rand::RngCore) {
+    let total_power = sources.light_power + sources.background_power;
+    if total_power <= 0.0 {
+        return;
+    }
+    let from_light = random(0.0, total_power, rng) < sources.light_power;
+    let emitted = match sources.bounds {
+        Some((center, radius)) if !from_light => emit_from_background(scene, &center, radius, rng),
+        _ => emit_from_light(scene, rng),
+    };
+    let Some((mut ray, beta)) = emitted else { return };
+    let probability = if from_light { sources.light_power } else { sources.background_power } / total_power;
+    let mut beta = beta / probability;
+
+    for depth in 0..camera.max_depth() {
+        let Some(hit_record) = first_hit(scene.world(), &ray) else { return };
+        // Light arriving straight from the lights and the background is found at the
+        // visible points.
+        if depth > 0 {
+            for &index in grid.pixels_near(hit_record.point()) {
+                pixels[index].add_photon(hit_record.point(), ray.dir(), &beta);
+            }
+        }
+
+        let Some(result) = hit_record.material().scatter(&ray, &hit_record, rng) else { return };
+        beta = beta * *result.attenuation();
+        ray = Ray::new(*result.scattered().orig(), *result.scattered().dir());
+    }
+}
+
+// A photon leaving one of the scene's lights, picked in proportion to its power, with its
+// flux.
+fn emit_from_light(scene: &Scene, rng: &mut dyn rand::RngCore) -> Option<(Ray, Color)> {
+    let (light, probability) = scene.lights().choose(rng)?;
+    let emission = light.sample_emission(rng)?;
+    if emission.pdf_position() <= 0.0 || emission.pdf_direction() <= 0.0 {
+        return None;
+    }
+    let cos = light.normal(emission.ray().orig()).map_or(1.0, |normal| normal.dot(emission.ray().dir()).abs());
+    let beta = *emission.radiance() * (cos / (probability * emission.pdf_position() * emission.pdf_direction()));
+    Some((Ray::new(*emission.ray().orig(), *emission.ray().dir()), beta))
+}
+
+// A photon arriving from the background in a uniformly picked direction, with its flux. It
+// starts on a disk facing that direction, as wide as the sphere with `center` and `radius`
+// and just outside it.
+fn emit_from_background(scene: &Scene, center: &Vec3, radius: f64, rng: &mut dyn rand::RngCore) -> Option<(Ray, Color)> {
+    let towards_background = Vec3::uniform_random_unit_vec(rng);
+    let disk = Vec3::uniform_random_in_unit_disk(rng);
+    let origin = *center + radius * (towards_background + Onb::new(&towards_background).to_world(&disk));
+    let area = std::f64::consts::PI * radius * radius;
+    let beta = scene.background(&towards_background) * (4.0 * std::f64::consts::PI * area);
+    Some((Ray::new(origin, -towards_background), beta))
+}
+
+fn first_hit(world: &HittableList, ray: &Ray) -> Option<HitRecord> {
+    world.hit(ray, &Interval::new(MIN_T_TO_PREVENT_SHADOW_ACNE, f64::MAX))
+}
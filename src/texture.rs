@@ -0,0 +1,77 @@
+// Textures give a color that varies over a surface. They are looked up with the surface
+// coordinates (u, v) of a hit and its position in world space.
+
+use crate::color::Color;
+use crate::vec3::Vec3;
+
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, point: &Vec3) -> Color;
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        Self {
+            albedo: albedo,
+        }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _: f64, _: f64, _: &Vec3) -> Color {
+        self.albedo
+    }
+}
+
+// Alternates between two colors in a grid over the (u, v) square.
+pub struct Checker {
+    u_count: f64,  // Number of checks across u
+    v_count: f64,  // Number of checks across v
+    even: Color,
+    odd: Color,
+}
+
+impl Checker {
+    pub fn new(u_count: u32, v_count: u32, even: Color, odd: Color) -> Self {
+        Self {
+            u_count: u_count.max(1) as f64,
+            v_count: v_count.max(1) as f64,
+            even: even,
+            odd: odd,
+        }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, _: &Vec3) -> Color {
+        let i = (u * self.u_count).floor() as i64;
+        let j = (v * self.v_count).floor() as i64;
+        if (i + j) % 2 == 0 { self.even } else { self.odd }
+    }
+}
+
+// A grid of smooth bumps over the (u, v) square, going from 0 to 1 and back.
+pub struct Waves {
+    u_count: f64,  // Number of bumps across u
+    v_count: f64,  // Number of bumps across v
+}
+
+impl Waves {
+    pub fn new(u_count: u32, v_count: u32) -> Self {
+        Self {
+            u_count: u_count.max(1) as f64,
+            v_count: v_count.max(1) as f64,
+        }
+    }
+}
+
+impl Texture for Waves {
+    fn value(&self, u: f64, v: f64, _: &Vec3) -> Color {
+        let tau = 2.0 * std::f64::consts::PI;
+        let h = 0.25 * (1.0 - (tau * u * self.u_count).cos()) * (1.0 - (tau * v * self.v_count).cos());
+        Color::new(h, h, h)
+    }
+}
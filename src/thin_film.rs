@@ -0,0 +1,158 @@
+// Thin-film interference, as seen on soap bubbles, oil slicks and anodized metal. Light
+// reflecting off the top and bottom of a film a few hundred nanometers thick interferes
+// with itself, so the reflectance depends strongly on wavelength and angle.
+//
+// The reflectance comes from the Airy summation over all internal reflections in the film,
+// averaged over s and p polarization. The substrate may be a conductor, so the maths is
+// done with complex refractive indices.
+
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+use std::ops;
+
+// Wavelengths in nanometers standing in for the red, green and blue channels when
+// rendering in RGB.
+const RGB_WAVELENGTHS_NM: [f64; 3] = [630.0, 532.0, 465.0];
+
+#[derive(Clone, Copy, Debug)]
+pub struct ThinFilm {
+    thickness_nm: f64,
+    refractive_index: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness_nm: f64, refractive_index: f64) -> Self {
+        Self {
+            thickness_nm: thickness_nm.max(0.0),
+            refractive_index: if refractive_index > 0.0 { refractive_index } else { 1.0 },
+        }
+    }
+
+    // Reflectance at the wavelengths carried by `ray`, or at the RGB stand-ins when it
+    // carries none. `cos_incident` is the cosine between the ray and the normal on the
+    // incident side, `incident_index` the refractive index on that side, and `substrate`
+    // the (n, k) of what's under the film, evaluated at the same wavelengths.
+    pub fn reflectance(&self, ray: &Ray, cos_incident: f64, incident_index: f64, substrate: &[(f64, f64); 3]) -> Vec3 {
+        let wavelengths = ray.wavelengths().map(|w| w.lambdas()).unwrap_or(RGB_WAVELENGTHS_NM);
+        let r = |i: usize| self.reflectance_at(wavelengths[i], cos_incident, incident_index, Complex::new(substrate[i].0, substrate[i].1));
+        Vec3::new(r(0), r(1), r(2))
+    }
+
+    fn reflectance_at(&self, wavelength_nm: f64, cos_incident: f64, incident_index: f64, substrate: Complex) -> f64 {
+        let n1 = Complex::new(incident_index, 0.0);
+        let n2 = Complex::new(self.refractive_index, 0.0);
+        let n3 = substrate;
+
+        // Snell's law with complex cosines, which also covers total internal reflection.
+        let cos1 = Complex::new(cos_incident.clamp(0.0, 1.0), 0.0);
+        let sin1_sq = Complex::new(1.0 - cos_incident * cos_incident, 0.0);
+        let cos_in = |n: Complex| (Complex::new(1.0, 0.0) - sin1_sq * (n1 / n) * (n1 / n)).sqrt();
+        let cos2 = cos_in(n2);
+        let cos3 = cos_in(n3);
+
+        // Round trip phase through the film.
+        let delta = Complex::new(4.0 * std::f64::consts::PI * self.thickness_nm / wavelength_nm, 0.0) * n2 * cos2;
+        let phase = (Complex::new(0.0, 1.0) * delta).exp();
+
+        let airy = |r12: Complex, r23: Complex| {
+            let r = (r12 + r23 * phase) / (Complex::new(1.0, 0.0) + r12 * r23 * phase);
+            r.norm_sq()
+        };
+        let s = airy(fresnel_s(n1, cos1, n2, cos2), fresnel_s(n2, cos2, n3, cos3));
+        let p = airy(fresnel_p(n1, cos1, n2, cos2), fresnel_p(n2, cos2, n3, cos3));
+        (0.5 * (s + p)).clamp(0.0, 1.0)
+    }
+}
+
+// Complex refractive index (n, k) of a metal whose reflectance at normal incidence is
+// `reflectivity`, using Gulbrandsen's "Artist Friendly Metallic Fresnel" (2014) with the
+// edge tint equal to the reflectivity.
+pub fn metal_index(reflectivity: f64) -> (f64, f64) {
+    let r = reflectivity.clamp(0.0, 0.99);
+    let g = r;
+    let sqrt_r = r.sqrt();
+    let n = g * (1.0 - r) / (1.0 + r) + (1.0 - g) * (1.0 + sqrt_r) / (1.0 - sqrt_r);
+    let k_sq = (r * (n + 1.0) * (n + 1.0) - (n - 1.0) * (n - 1.0)) / (1.0 - r);
+    (n, k_sq.max(0.0).sqrt())
+}
+
+fn fresnel_s(n_i: Complex, cos_i: Complex, n_t: Complex, cos_t: Complex) -> Complex {
+    (n_i * cos_i - n_t * cos_t) / (n_i * cos_i + n_t * cos_t)
+}
+
+fn fresnel_p(n_i: Complex, cos_i: Complex, n_t: Complex, cos_t: Complex) -> Complex {
+    (n_t * cos_i - n_i * cos_t) / (n_t * cos_i + n_i * cos_t)
+}
+
+// Just enough complex arithmetic for the Fresnel equations.
+#[derive(Clone, Copy, Debug)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self {
+            re: re,
+            im: im,
+        }
+    }
+
+    fn norm_sq(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // Principal square root.
+    fn sqrt(&self) -> Self {
+        let r = self.norm_sq().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn exp(&self) -> Self {
+        let magnitude = self.re.exp();
+        Complex::new(magnitude * self.im.cos(), magnitude * self.im.sin())
+    }
+}
+
+impl ops::Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl ops::Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl ops::Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Complex::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+impl ops::Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        let denominator = rhs.norm_sq();
+        if denominator == 0.0 {
+            return Complex::new(0.0, 0.0);
+        }
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / denominator,
+            (self.im * rhs.re - self.re * rhs.im) / denominator,
+        )
+    }
+}
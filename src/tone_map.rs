@@ -0,0 +1,141 @@
+// Exposure and tone mapping, which bring the linear radiance the renderer computes into the
+// [0, 1] range an 8-bit image can show. Without tone mapping everything brighter than 1 is
+// clipped, so lights and their reflections turn into flat patches of white.
+
+use crate::color::Color;
+use crate::color_space::{Matrix, transform};
+
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ToneMapper {
+    // Clip each channel at 1.
+    Clamp,
+    // L / (1 + L) on the luminance L, keeping the hue. Never quite reaches white.
+    Reinhard,
+    // Reinhard's curve stretched so that the white point maps to white.
+    ReinhardExtended,
+    // John Hable's filmic curve from Uncharted 2, per channel.
+    Hable,
+    // Stephen Hill's fit of the ACES reference and output transforms.
+    Aces,
+    // Troy Sobotka's AgX, after the polynomial fit by Benjamin Wrensch. Bright, saturated
+    // colors fade towards white instead of skewing in hue.
+    Agx,
+}
+
+pub struct ToneMapping {
+    mapper: ToneMapper,
+    exposure: f64,  // In stops
+    white_point: Option<f64>,  // Luminance that becomes white with extended Reinhard
+}
+
+impl ToneMapping {
+    // With no `white_point`, extended Reinhard uses the brightest pixel's.
+    pub fn new(mapper: ToneMapper, exposure: f64, white_point: Option<f64>) -> Self {
+        Self {
+            mapper: mapper,
+            exposure: exposure,
+            white_point: white_point,
+        }
+    }
+
+    // Applies the exposure and then the tone mapper to `pixels`, linear colors in, linear
+    // colors in [0, 1] out, ready for output encoding.
+    pub fn apply(&self, pixels: &[Color]) -> Vec<Color> {
+        let scale = 2.0_f64.powf(self.exposure);
+        let exposed: Vec<Color> = pixels.iter().map(|pixel| *pixel * scale).collect();
+        let white_point = self.white_point.unwrap_or_else(|| exposed.iter().map(luminance).fold(0.0, f64::max));
+        exposed.iter().map(|pixel| self.map(pixel, white_point)).collect()
+    }
+
+    fn map(&self, color: &Color, white_point: f64) -> Color {
+        let mapped = match self.mapper {
+            ToneMapper::Clamp => *color,
+            ToneMapper::Reinhard => with_luminance(color, |l| l / (1.0 + l)),
+            ToneMapper::ReinhardExtended => {
+                let white_sq = (white_point * white_point).max(f64::MIN_POSITIVE);
+                with_luminance(color, |l| l * (1.0 + l / white_sq) / (1.0 + l))
+            },
+            ToneMapper::Hable => {
+                const EXPOSURE_BIAS: f64 = 2.0;
+                const WHITE: f64 = 11.2;
+                let scale = 1.0 / hable(WHITE);
+                map_channels(color, |x| hable(EXPOSURE_BIAS * x) * scale)
+            },
+            ToneMapper::Aces => {
+                let color = transform(&ACES_INPUT, color);
+                let color = map_channels(&color, |x| (x * (x + 0.0245786) - 0.000090537) / (x * (0.983729 * x + 0.4329510) + 0.238081));
+                transform(&ACES_OUTPUT, &color)
+            },
+            ToneMapper::Agx => agx(color),
+        };
+        map_channels(&mapped, |x| x.clamp(0.0, 1.0))
+    }
+}
+
+fn luminance(color: &Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+// Scales `color` so that its luminance becomes `f` of what it was.
+fn with_luminance(color: &Color, f: impl Fn(f64) -> f64) -> Color {
+    let l = luminance(color);
+    if l <= 0.0 { Color::new(0.0, 0.0, 0.0) } else { *color * (f(l) / l) }
+}
+
+fn map_channels(color: &Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(color.x()), f(color.y()), f(color.z()))
+}
+
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15;  // Shoulder strength
+    const B: f64 = 0.50;  // Linear strength
+    const C: f64 = 0.10;  // Linear angle
+    const D: f64 = 0.20;  // Toe strength
+    const E: f64 = 0.02;  // Toe numerator
+    const F: f64 = 0.30;  // Toe denominator
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+// Linear sRGB to the ACES fit's input space, with the reference transform's exposure folded
+// in, and back.
+const ACES_INPUT: Matrix = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+const ACES_OUTPUT: Matrix = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+// Linear sRGB to AgX's inset primaries, which desaturate the brightest colors, and back.
+const AGX_INSET: Matrix = [
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+];
+const AGX_OUTSET: Matrix = [
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+];
+
+fn agx(color: &Color) -> Color {
+    // The range of stops around middle gray that the curve covers.
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+    let color = transform(&AGX_INSET, color);
+    let color = map_channels(&color, |x| {
+        let x = (x.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+    });
+    // The curve's output is display encoded with a gamma of 2.2, which is undone here since
+    // output encoding comes later.
+    let color = transform(&AGX_OUTSET, &color);
+    map_channels(&color, |x| x.max(0.0).powf(2.2))
+}
@@ -59,7 +59,7 @@ impl Vec3 {
 
     // Generates a unit 3D vector lying in the unit sphere. Uses rejection
     // sampling to ensure a uniform probability distribution.
-    pub fn uniform_random_unit_vec(rng: &mut rand::rngs::ThreadRng) -> Self {
+    pub fn uniform_random_unit_vec(rng: &mut dyn rand::RngCore) -> Self {
         loop {
             let result = Vec3::new(random(-1.0, 1.0, rng), random(-1.0, 1.0, rng), random(-1.0, 1.0, rng));
             // Also reject vectors very close to the origin to prevent rounding
@@ -72,7 +72,7 @@ impl Vec3 {
 
     // Generates a point lying inside a unit disk. Uses rejection sampling to
     // ensure a uniform probability distribution.
-    pub fn uniform_random_in_unit_disk(rng: &mut rand::rngs::ThreadRng) -> Self {
+    pub fn uniform_random_in_unit_disk(rng: &mut dyn rand::RngCore) -> Self {
         loop {
             let result = Vec3::new(random(-1.0, 1.0, rng), random(-1.0, 1.0, rng), 0.0);
             if result.len_sq() < 1.0 {
@@ -82,7 +82,7 @@ impl Vec3 {
     }
 
     pub fn is_near_zero(&self) -> bool {
-        self.x < NEAR_ZERO_TOLERANCE && self.y < NEAR_ZERO_TOLERANCE && self.z < NEAR_ZERO_TOLERANCE
+        self.x.abs() < NEAR_ZERO_TOLERANCE && self.y.abs() < NEAR_ZERO_TOLERANCE && self.z.abs() < NEAR_ZERO_TOLERANCE
     }
 
     pub fn cross(&self, rhs: &Self) -> Self {
@@ -93,7 +93,7 @@ impl Vec3 {
         }
     }
 
-    pub fn random_vec(each_min: f64, each_max: f64, rng: &mut rand::rngs::ThreadRng) -> Self {
+    pub fn random_vec(each_min: f64, each_max: f64, rng: &mut dyn rand::RngCore) -> Self {
         Self {
             x: random(each_min, each_max, rng),
             y: random(each_min, each_max, rng),